@@ -0,0 +1,9 @@
+use std::env;
+
+use runpod::serverless::modules::logging::{log_secret, LogLevel};
+
+fn main() {
+    log_secret("RUNPOD_AI_API_KEY", env::var("RUNPOD_AI_API_KEY").ok(), LogLevel::Info);
+    log_secret("RUNPOD_WEBHOOK_GET_JOB", env::var("RUNPOD_WEBHOOK_GET_JOB").ok(), LogLevel::Info);
+    log_secret("RUNPOD_WEBHOOK_POST_OUTPUT", env::var("RUNPOD_WEBHOOK_POST_OUTPUT").ok(), LogLevel::Info);
+}
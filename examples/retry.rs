@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use runpod::serverless::modules::retry::retry;
+
+async fn error_prone_function() -> Result<(), &'static str> {
+    Err("An error occurred")
+}
+
+#[tokio::main]
+async fn main() {
+    let max_attempts = 3;
+    let base_delay = Duration::from_secs(1);
+    let max_delay = Duration::from_secs(3);
+
+    match retry(error_prone_function, max_attempts, base_delay, max_delay).await {
+        Ok(_) => println!("Success!"),
+        Err(err) => println!("Error: {}", err),
+    }
+}
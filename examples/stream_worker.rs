@@ -0,0 +1,22 @@
+use futures::stream::{self, Stream};
+use runpod::serverless::{JobContext, StartConfig};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Input {
+    prompt: String,
+}
+
+fn handler(input: Input, _context: JobContext) -> impl Stream<Item = Result<String, String>> {
+    let words: Vec<String> = input.prompt.split_whitespace().map(str::to_string).collect();
+    stream::iter(words.into_iter().map(Ok))
+}
+
+#[tokio::main]
+async fn main() {
+    let config = StartConfig {
+        return_aggregate_stream: true,
+        ..StartConfig::default()
+    };
+    runpod::serverless::start_stream(handler, config).await;
+}
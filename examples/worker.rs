@@ -0,0 +1,27 @@
+use runpod::serverless::{JobContext, StartConfig};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, JsonSchema)]
+struct Input {
+    /// Text echoed back by the handler.
+    prompt: String,
+}
+
+#[derive(Serialize)]
+struct Output {
+    echo: String,
+}
+
+async fn handler(input: Input, _context: JobContext) -> Result<Output, String> {
+    if input.prompt.is_empty() {
+        return Err("prompt must not be empty".to_string());
+    }
+    Ok(Output { echo: input.prompt })
+}
+
+#[tokio::main]
+async fn main() {
+    let config = StartConfig::default().with_input_schema::<Input>();
+    runpod::serverless::start_with_config(handler, config).await;
+}
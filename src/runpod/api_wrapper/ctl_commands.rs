@@ -2,12 +2,11 @@
 
 use super::graphql::run_graphql_query;
 use super::mutations::pods::{self, PodCreateInput};
-use super::queries::gpus::{self, GpuQuery};
+use super::queries::gpus;
 use serde_json::Value;
-use std::collections::HashMap;
 
 pub async fn get_gpus() -> Result<Vec<Value>, reqwest::Error> {
-    let raw_return = run_graphql_query(&gpus::QUERY_GPU_TYPES).await?;
+    let raw_return = run_graphql_query(gpus::QUERY_GPU_TYPES).await?;
     let cleaned_return = raw_return["data"]["gpuTypes"].as_array().unwrap().clone();
     Ok(cleaned_return)
 }
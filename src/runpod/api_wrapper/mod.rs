@@ -0,0 +1,4 @@
+pub mod ctl_commands;
+pub mod graphql;
+pub mod mutations;
+pub mod queries;
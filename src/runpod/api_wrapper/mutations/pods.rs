@@ -1,6 +1,5 @@
 // src/api_wrapper/mutations/pods.rs
 
-use serde_json::Value;
 use std::collections::HashMap;
 
 #[derive(Debug)]
@@ -1,8 +1,10 @@
 use std::collections::HashMap;
-use reqwest::{Client, Response};
+use reqwest::Client;
 use serde_json::Value;
 use tokio::time::{sleep, Duration};
 
+use crate::endpoint::runner::JobStatus;
+
 pub struct Job {
     pub endpoint_id: String,
     pub job_id: String,
@@ -11,16 +13,15 @@ pub struct Job {
 }
 
 impl Job {
-    pub async fn status(&self) -> String {
+    pub async fn status(&self) -> JobStatus {
         let response = self.client.get(&self.status_url).send().await.unwrap();
         let json: Value = response.json().await.unwrap();
-        json["status"].as_str().unwrap().to_string()
+        JobStatus::from_response(&json)
     }
 
     pub async fn output(self) -> Value {
         loop {
-            let status = self.status().await;
-            if status == "COMPLETED" || status == "FAILED" {
+            if self.status().await.is_final() {
                 break;
             }
             sleep(Duration::from_secs(1)).await;
@@ -33,22 +34,41 @@ impl Job {
 
 pub struct Endpoint {
     pub endpoint_id: String,
-    pub endpoint_url: String,
+    /// Base URL of the endpoint API, see [`crate::endpoint::Endpoint::base_url`].
+    pub base_url: String,
     pub client: Client,
 }
 
 impl Endpoint {
+    pub fn new(endpoint_id: impl Into<String>, client: Client) -> Self {
+        Endpoint {
+            endpoint_id: endpoint_id.into(),
+            base_url: crate::ENDPOINT_URL_BASE.to_string(),
+            client,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// URL of `route` on this endpoint.
+    pub fn url(&self, route: &str) -> String {
+        format!("{}/{}/{}", self.base_url.trim_end_matches('/'), self.endpoint_id, route)
+    }
+
     pub async fn run(&self, endpoint_input: HashMap<String, Value>) -> Job {
         let response = self
             .client
-            .post(&self.endpoint_url)
+            .post(self.url("run"))
             .json(&json!({ "input": endpoint_input }))
             .send()
             .await
             .unwrap();
         let json_resp: Value = response.json().await.unwrap();
         let job_id = json_resp["id"].as_str().unwrap().to_string();
-        let status_url = format!("{}/{}/status/{}", crate::ENDPOINT_URL_BASE, self.endpoint_id, job_id);
+        let status_url = self.url(&format!("status/{}", job_id));
         Job {
             endpoint_id: self.endpoint_id.clone(),
             job_id,
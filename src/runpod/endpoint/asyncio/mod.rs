@@ -0,0 +1 @@
+pub mod asyncio_runner;
@@ -0,0 +1,4 @@
+pub mod asyncio;
+pub mod runner;
+
+pub use runner::{Endpoint, Job, JobStatus};
@@ -1,9 +1,34 @@
 use std::collections::HashMap;
-use reqwest::{Client, Response};
+use reqwest::blocking::Client;
 use serde_json::Value;
 use std::time::Duration;
 use std::thread;
 
+/// Status of a submitted job as reported by the `/status` endpoint.
+#[derive(Debug, Clone)]
+pub struct JobStatus {
+    pub status: String,
+    /// Latest progress update posted by the worker while the job is `IN_PROGRESS`.
+    pub progress: Option<Value>,
+}
+
+impl JobStatus {
+    pub fn from_response(json: &Value) -> Self {
+        let status = json["status"].as_str().unwrap_or_default().to_string();
+        let progress = if status == "IN_PROGRESS" && !json["output"].is_null() {
+            Some(json["output"].clone())
+        } else {
+            None
+        };
+        JobStatus { status, progress }
+    }
+
+    /// Whether the job has reached a status it won't leave again.
+    pub fn is_final(&self) -> bool {
+        matches!(self.status.as_str(), "COMPLETED" | "FAILED" | "CANCELLED" | "TIMED_OUT")
+    }
+}
+
 pub struct Job {
     pub endpoint_id: String,
     pub job_id: String,
@@ -12,14 +37,17 @@ pub struct Job {
 }
 
 impl Job {
-    pub fn status(&self) -> String {
+    pub fn status(&self) -> JobStatus {
         let response = self.client.get(&self.status_url).send().unwrap();
         let json: Value = response.json().unwrap();
-        json["status"].as_str().unwrap().to_string()
+        JobStatus::from_response(&json)
     }
 
-    pub fn output(mut self) -> Value {
-        while self.status() != "COMPLETED" && self.status() != "FAILED" {
+    pub fn output(self) -> Value {
+        loop {
+            if self.status().is_final() {
+                break;
+            }
             thread::sleep(Duration::from_millis(100));
         }
         let response = self.client.get(&self.status_url).send().unwrap();
@@ -30,21 +58,43 @@ impl Job {
 
 pub struct Endpoint {
     pub endpoint_id: String,
-    pub endpoint_url: String,
+    /// Base URL of the endpoint API, requests going to `{base_url}/{endpoint_id}/...`.
+    ///
+    /// Defaults to [`crate::ENDPOINT_URL_BASE`]; set it to the address of a worker started with
+    /// `--rp_serve_api`, e.g. `http://localhost:8000`, to run jobs on its local API instead.
+    pub base_url: String,
     pub client: Client,
 }
 
 impl Endpoint {
+    pub fn new(endpoint_id: impl Into<String>, client: Client) -> Self {
+        Endpoint {
+            endpoint_id: endpoint_id.into(),
+            base_url: crate::ENDPOINT_URL_BASE.to_string(),
+            client,
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// URL of `route` on this endpoint.
+    pub fn url(&self, route: &str) -> String {
+        format!("{}/{}/{}", self.base_url.trim_end_matches('/'), self.endpoint_id, route)
+    }
+
     pub fn run(&self, endpoint_input: HashMap<String, Value>) -> Job {
         let response = self
             .client
-            .post(&self.endpoint_url)
+            .post(self.url("run"))
             .json(&json!({ "input": endpoint_input }))
             .send()
             .unwrap();
         let json_resp: Value = response.json().unwrap();
         let job_id = json_resp["id"].as_str().unwrap().to_string();
-        let status_url = format!("{}/{}/status/{}", crate::ENDPOINT_URL_BASE, self.endpoint_id, job_id);
+        let status_url = self.url(&format!("status/{}", job_id));
         Job {
             endpoint_id: self.endpoint_id.clone(),
             job_id,
@@ -56,7 +106,7 @@ impl Endpoint {
     pub fn run_sync(&self, endpoint_input: HashMap<String, Value>) -> Value {
         let response = self
             .client
-            .post(&self.endpoint_url)
+            .post(self.url("runsync"))
             .json(&json!({ "input": endpoint_input }))
             .send()
             .unwrap();
@@ -0,0 +1,15 @@
+//! Rust implementation of runpod-python.
+//!
+//! - [`serverless`] contains the worker side: job polling, heartbeat and result posting.
+//! - [`endpoint`] is the client for calling deployed serverless endpoints.
+//! - [`api_wrapper`] wraps the RunPod GraphQL API for managing pods and GPUs.
+
+#[macro_use]
+extern crate serde_json;
+
+pub mod api_wrapper;
+pub mod endpoint;
+pub mod serverless;
+
+/// Base URL of the serverless endpoint API.
+pub const ENDPOINT_URL_BASE: &str = "https://api.runpod.ai/v2";
@@ -0,0 +1,8 @@
+pub mod modules;
+pub mod utils;
+pub mod worker;
+
+pub use modules::job_context::JobContext;
+pub use modules::job_result::JobResult;
+pub use modules::worker_config::{ConfigError, WorkerConfig};
+pub use worker::{start, start_stream, start_with_config, StartConfig, REFRESH_EXIT_CODE};
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future;
+use log::{debug, info, warn};
+use reqwest::Client;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+use super::worker_config::WorkerConfig;
+use super::worker_state::{cancel_job, get_job_ids};
+
+/// Time between two polls of the status of the jobs the worker holds.
+pub const CANCEL_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Fetches the status of every job the worker holds from the job status endpoint, cancelling
+/// the jobs reported as `CANCELLED`. Does nothing when no status endpoint is configured.
+pub async fn poll_cancellations(client: Arc<Client>, config: &WorkerConfig) {
+    let polls = get_job_ids().into_iter().filter_map(|job_id| {
+        let client = client.clone();
+        let url = config.status_url(&job_id)?;
+        Some(async move {
+            let response = match client.get(&url).send().await.and_then(|res| res.error_for_status()) {
+                Ok(response) => response,
+                Err(err) => {
+                    warn!("Error while polling the status of job {}: {:?}", job_id, err);
+                    return;
+                }
+            };
+            match response.json::<Value>().await {
+                Ok(body) if body["status"] == "CANCELLED" => {
+                    if cancel_job(&job_id) {
+                        info!("Job {} cancelled", job_id);
+                    }
+                }
+                Ok(body) => debug!("Job {} status: {}", job_id, body["status"]),
+                Err(err) => warn!("Error while reading the status of job {}: {:?}", job_id, err),
+            }
+        })
+    });
+    future::join_all(polls).await;
+}
+
+/// Starts a task polling the status of the held jobs every [`CANCEL_POLL_INTERVAL`].
+pub fn start_cancel_poll(client: Arc<Client>, config: Arc<WorkerConfig>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            sleep(CANCEL_POLL_INTERVAL).await;
+            poll_cancellations(client.clone(), &config).await;
+        }
+    })
+}
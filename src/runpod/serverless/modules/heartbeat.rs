@@ -1,45 +1,144 @@
 use std::collections::HashMap;
-use std::env;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use std::thread;
 
-use reqwest::{Client, StatusCode};
+use rand::Rng;
+use reqwest::Client;
+use serde_json::Value;
 use log::{info, error, debug};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 
-use crate::worker_state::{get_current_job_id, PING_URL, PING_INTERVAL};
+use super::worker_config::WorkerConfig;
+use super::worker_state::{cancel_job, get_job_ids};
 
-pub fn send_ping(client: Arc<Client>, ping_params: Option<HashMap<String, String>>) {
-    if let Some(ping_url) = PING_URL.as_ref() {
-        let result = match client.get(ping_url).query(&ping_params).send() {
+/// Consecutive failed pings after which the failure hook is called.
+pub const DEFAULT_MAX_FAILURES: u32 = 5;
+
+/// Handle to the background heartbeat task started by [`start_ping`].
+pub struct Heartbeat {
+    cancel: CancellationToken,
+    task: JoinHandle<()>,
+    consecutive_failures: Arc<AtomicU32>,
+}
+
+impl Heartbeat {
+    /// Number of pings that have failed since the last successful one.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures.load(Ordering::Relaxed)
+    }
+
+    /// Stops the heartbeat and waits for the task to finish.
+    pub async fn stop(self) {
+        self.cancel.cancel();
+        let _ = self.task.await;
+    }
+}
+
+pub async fn send_ping(
+    client: Arc<Client>,
+    config: &WorkerConfig,
+    ping_params: Option<HashMap<String, String>>,
+) -> Result<(), reqwest::Error> {
+    if let Some(ping_url) = &config.ping_url {
+        let result = match client.get(ping_url).query(&ping_params).send().await.and_then(|res| res.error_for_status()) {
             Ok(res) => res,
             Err(err) => {
                 error!("Heartbeat Failed  URL: {}  Params: {:?}", ping_url, ping_params);
                 error!("Heartbeat Fail  Error: {:?}", err);
-                return;
+                return Err(err);
             }
         };
 
         info!("Heartbeat Sent  URL: {}  Status: {:?}", ping_url, result.status());
-        info!("Heartbeat Sent  Interval: {}ms  Params: {:?}", PING_INTERVAL.as_millis(), ping_params);
+        info!("Heartbeat Sent  Interval: {:?}  Params: {:?}", config.ping_interval, ping_params);
+
+        // The local API lists jobs cancelled since the last heartbeat in the ping response;
+        // cancellations on the platform are picked up by `cancellation::start_cancel_poll`.
+        if let Ok(body) = result.json::<Value>().await {
+            for job_id in body["cancelled_job_ids"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                if cancel_job(job_id) {
+                    info!("Job {} cancelled", job_id);
+                }
+            }
+        }
     }
+    Ok(())
 }
 
-pub fn start_ping(client: Arc<Client>) {
-    let job_id = get_current_job_id();
+/// Starts the heartbeat task, reporting the in-flight job ids every `config.ping_interval`.
+pub fn start_ping(client: Arc<Client>, config: Arc<WorkerConfig>) -> Heartbeat {
+    start_ping_with_hook(client, config, DEFAULT_MAX_FAILURES, |_| {})
+}
 
-    let mut ping_params = HashMap::new();
-    if let Some(job_id) = job_id {
-        ping_params.insert("job_id".to_string(), job_id);
-    }
+/// Same as [`start_ping`], calling `on_failure` with the failure count once `max_failures`
+/// consecutive pings have failed, and again for every further failure.
+pub fn start_ping_with_hook<H>(
+    client: Arc<Client>,
+    config: Arc<WorkerConfig>,
+    max_failures: u32,
+    on_failure: H,
+) -> Heartbeat
+where
+    H: Fn(u32) + Send + 'static,
+{
+    let cancel = CancellationToken::new();
+    let consecutive_failures = Arc::new(AtomicU32::new(0));
 
-    send_ping(client.clone(), Some(ping_params));
+    let task = {
+        let cancel = cancel.clone();
+        let consecutive_failures = consecutive_failures.clone();
+        tokio::spawn(async move {
+            loop {
+                let failures = consecutive_failures.load(Ordering::Relaxed);
 
-    debug!("Scheduling next heartbeat in {}ms", PING_INTERVAL.as_millis());
-    thread::spawn(move || {
-        loop {
-            thread::sleep(PING_INTERVAL);
-            start_ping(client.clone());
-        }
-    });
+                let mut ping_params = HashMap::new();
+                let job_ids = get_job_ids();
+                if !job_ids.is_empty() {
+                    ping_params.insert("job_id".to_string(), job_ids.join(","));
+                }
+                if failures > 0 {
+                    ping_params.insert("retry_ping".to_string(), "1".to_string());
+                }
+
+                let ping = tokio::select! {
+                    ping = send_ping(client.clone(), &config, Some(ping_params)) => ping,
+                    _ = cancel.cancelled() => break,
+                };
+
+                match ping {
+                    Ok(()) => consecutive_failures.store(0, Ordering::Relaxed),
+                    Err(_) => {
+                        let failures = failures + 1;
+                        consecutive_failures.store(failures, Ordering::Relaxed);
+                        if failures >= max_failures {
+                            on_failure(failures);
+                        }
+                    }
+                }
+
+                let interval = jittered(config.ping_interval);
+                debug!("Scheduling next heartbeat in {}ms", interval.as_millis());
+                tokio::select! {
+                    _ = sleep(interval) => {}
+                    _ = cancel.cancelled() => break,
+                }
+            }
+            debug!("Heartbeat stopped");
+        })
+    };
+
+    Heartbeat {
+        cancel,
+        task,
+        consecutive_failures,
+    }
+}
+
+/// Spreads pings by ±10% so workers started together don't ping in lockstep.
+fn jittered(interval: Duration) -> Duration {
+    let jitter = rand::thread_rng().gen_range(0.9..1.1);
+    interval.mul_f64(jitter)
 }
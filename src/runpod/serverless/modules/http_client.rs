@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{Client, Proxy};
+
+use super::worker_config::WorkerConfig;
+
+/// User agent sent with every worker request.
+pub const USER_AGENT: &str = concat!("runpod-rust/", env!("CARGO_PKG_VERSION"));
+
+/// Settings of the HTTP client shared by job fetching, heartbeats and result posting.
+#[derive(Debug, Clone)]
+pub struct HttpClientConfig {
+    /// Value of the `Authorization` header, the worker's `api_key` when unset.
+    pub api_key: Option<String>,
+    /// Time allowed to establish a connection.
+    pub connect_timeout: Duration,
+    /// Time allowed for a whole request, including reading the response.
+    pub read_timeout: Duration,
+    /// How long idle pooled connections are kept open.
+    pub pool_idle_timeout: Duration,
+    /// Maximum number of idle pooled connections per host.
+    pub pool_max_idle_per_host: usize,
+    /// Proxy URL all requests go through.
+    pub proxy: Option<String>,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        HttpClientConfig {
+            api_key: None,
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(300),
+            pool_idle_timeout: Duration::from_secs(90),
+            pool_max_idle_per_host: 8,
+            proxy: None,
+        }
+    }
+}
+
+impl HttpClientConfig {
+    /// Builds the client for the worker configured by `config`, authenticating with the
+    /// worker's API key unless `api_key` is set.
+    pub fn build_for(&self, config: &WorkerConfig) -> Result<Client> {
+        let mut client_config = self.clone();
+        if client_config.api_key.is_none() {
+            client_config.api_key = config.api_key.clone();
+        }
+        client_config.build()
+    }
+
+    /// Builds the client, failing on an invalid API key or proxy URL.
+    pub fn build(&self) -> Result<Client> {
+        let mut headers = HeaderMap::new();
+        if let Some(api_key) = &self.api_key {
+            let mut auth_header = HeaderValue::from_str(api_key).context("API key is not a valid header value")?;
+            auth_header.set_sensitive(true);
+            headers.insert(AUTHORIZATION, auth_header);
+        }
+
+        let mut builder = Client::builder()
+            .user_agent(USER_AGENT)
+            .default_headers(headers)
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.read_timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// Builds the client for the worker configured by `config`, with the default settings.
+pub fn worker_client(config: &WorkerConfig) -> Result<Client> {
+    HttpClientConfig::default().build_for(config)
+}
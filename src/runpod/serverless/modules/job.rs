@@ -1,75 +1,284 @@
-use std::collections::HashMap;
+use std::fmt::Display;
 use std::fs;
-use std::sync::Arc;
-use std::time::Instant;
+use std::future::Future;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Once};
+use std::time::{Duration, Instant};
 
+use anyhow::{bail, Context};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use jsonschema::JSONSchema;
+use futures::{future, FutureExt, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use serde_json::Value;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
 use reqwest::{Client, StatusCode};
 use log::{info, warn, error, debug};
 
-use crate::worker_state::{JOB_GET_URL, get_done_url};
-use crate::retry::{retry, RetryConfig};
-use crate::rp_tips::check_return_size;
+use super::job_context::{remove_scratch_dir, JobContext};
+use super::job_error::{install_panic_hook, JobError};
+use super::job_result::JobResult;
+use super::outbox;
+use super::worker_config::WorkerConfig;
+use super::retry::retry;
+use super::rp_tips::check_return_size;
 
-pub async fn get_local() -> Option<Value> {
-    if let Ok(content) = fs::read_to_string("test_input.json") {
-        if let Ok(test_inputs) = serde_json::from_str(&content) {
-            let mut test_inputs: Value = test_inputs;
-            if !test_inputs.as_object().unwrap().contains_key("id") {
-                test_inputs.as_object_mut().unwrap().insert("id".into(), "local_test".into());
+/// Loads the local test jobs from `--test_input`, or from `test_input.json` when it isn't given.
+///
+/// The test input is a job such as `{"input": {...}}` or a list of them, given inline as JSON or
+/// as the path of a file holding it. Jobs without an `id` are given a `local_test` one, and jobs
+/// may carry an `expected_output` checked by [`check_local_result`].
+pub fn get_local(config: &WorkerConfig) -> anyhow::Result<Vec<Value>> {
+    let content = match &config.test_input {
+        Some(test_input) if test_input.trim_start().starts_with(['{', '[']) => test_input.clone(),
+        Some(path) => fs::read_to_string(path).with_context(|| format!("could not read test input file {}", path))?,
+        None => fs::read_to_string("test_input.json").context("no --test_input given and test_input.json not found")?,
+    };
+    let (test_cases, is_list) = match serde_json::from_str(&content).context("test input is not valid JSON")? {
+        Value::Array(test_cases) => (test_cases, true),
+        test_case => (vec![test_case], false),
+    };
+    if test_cases.is_empty() {
+        bail!("test input holds no test cases");
+    }
+
+    let mut jobs = Vec::with_capacity(test_cases.len());
+    for (index, mut test_case) in test_cases.into_iter().enumerate() {
+        let job = match test_case.as_object_mut() {
+            Some(job) => job,
+            None => bail!("test case {} is not a JSON object: {}", index + 1, test_case),
+        };
+        if !job.contains_key("id") {
+            let job_id = if is_list { format!("local_test_{}", index + 1) } else { "local_test".to_string() };
+            job.insert("id".to_string(), job_id.into());
+        }
+        debug!("Retrieved local job: {:?}", test_case);
+        jobs.push(test_case);
+    }
+    Ok(jobs)
+}
+
+/// How the output of a local test job is checked against its `expected_output`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpectedOutput {
+    /// The output must equal the value.
+    Exact(Value),
+    /// The output must equal the value, except that its objects may hold additional keys.
+    Subset(Value),
+    /// The output must validate against the JSON schema.
+    Schema(Value),
+}
+
+impl ExpectedOutput {
+    /// Reads the `expected_output` of a test job, `None` when it has none.
+    ///
+    /// `{"exact": ...}`, `{"subset": ...}` and `{"schema": ...}` select how the output is
+    /// checked; any other value must be matched exactly.
+    pub fn from_job(job: &Value) -> Option<Self> {
+        let expected_output = job.get("expected_output")?;
+
+        let mut entries = expected_output.as_object().into_iter().flatten();
+        if let (Some((mode, value)), None) = (entries.next(), entries.next()) {
+            match mode.as_str() {
+                "exact" => return Some(ExpectedOutput::Exact(value.clone())),
+                "subset" => return Some(ExpectedOutput::Subset(value.clone())),
+                "schema" => return Some(ExpectedOutput::Schema(value.clone())),
+                _ => {}
             }
-            debug!("Retrieved local job: {:?}", test_inputs);
-            return Some(test_inputs);
         }
+        Some(ExpectedOutput::Exact(expected_output.clone()))
+    }
+
+    /// Differences between `output` and the expectation, empty when the output matches.
+    pub fn diff(&self, output: &Value) -> Vec<String> {
+        let mut diffs = Vec::new();
+        match self {
+            ExpectedOutput::Exact(expected) => diff_values(expected, output, false, "", &mut diffs),
+            ExpectedOutput::Subset(expected) => diff_values(expected, output, true, "", &mut diffs),
+            ExpectedOutput::Schema(schema) => match JSONSchema::compile(schema) {
+                Ok(schema) => {
+                    if let Err(errors) = schema.validate(output) {
+                        for err in errors {
+                            let path = err.instance_path.to_string();
+                            diffs.push(format!("{}: {}", display_path(&path), err));
+                        }
+                    }
+                }
+                Err(err) => diffs.push(format!("invalid schema: {}", err)),
+            },
+        }
+        diffs
     }
-    warn!("test_input.json not found, skipping local testing");
-    None
 }
 
-pub async fn get_job(client: Arc<Client>) -> Option<Value> {
-    let next_job: Value;
+/// Checks the result of a local test job against its `expected_output`, printing whether the
+/// job passed and the differences found. Jobs without an expectation pass unless they failed.
+pub fn check_local_result(job: &Value, job_result: &JobResult) -> bool {
+    let expected_output = match ExpectedOutput::from_job(job) {
+        Some(expected_output) => expected_output,
+        None => return !job_result.is_error(),
+    };
 
-    if let Some(job_get_url) = JOB_GET_URL.as_ref() {
-        let response = match client.get(job_get_url).send().await {
-            Ok(resp) => resp,
-            Err(err) => {
-                error!("Error while getting job: {:?}", err);
-                return None;
+    let diffs = match job_result.output() {
+        Some(output) => expected_output.diff(&output),
+        None => vec!["job produced no output".to_string()],
+    };
+    if diffs.is_empty() {
+        println!("PASS {}", job_id(job));
+        return true;
+    }
+
+    println!("FAIL {}", job_id(job));
+    for diff in diffs {
+        println!("  {}", diff);
+    }
+    false
+}
+
+/// Collects the differences between `expected` and `actual` under the JSON pointer `path`,
+/// allowing additional object keys in `actual` when `subset` is set.
+fn diff_values(expected: &Value, actual: &Value, subset: bool, path: &str, diffs: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                let path = format!("{}/{}", path, key);
+                match actual.get(key) {
+                    Some(actual) => diff_values(expected, actual, subset, &path, diffs),
+                    None => diffs.push(format!("{}: missing, expected {}", path, expected)),
+                }
             }
-        };
-        next_job = match response.json().await {
-            Ok(json) => json,
-            Err(err) => {
-                error!("Error while parsing job JSON: {:?}", err);
-                return None;
+            if !subset {
+                for (key, actual) in actual.iter().filter(|(key, _)| !expected.contains_key(*key)) {
+                    diffs.push(format!("{}/{}: unexpected {}", path, key, actual));
+                }
             }
-        };
+        }
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => {
+            for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                diff_values(expected, actual, subset, &format!("{}/{}", path, index), diffs);
+            }
+        }
+        _ if expected == actual => {}
+        _ => diffs.push(format!("{}: expected {}, got {}", display_path(path), expected, actual)),
+    }
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() {
+        "/"
     } else {
-        warn!("RUNPOD_WEBHOOK_GET_JOB not set, switching to get_local");
-        return get_local().await;
+        path
     }
+}
 
-    info!("Received job: {:?}", next_job["id"]);
-    Some(next_job)
+pub async fn get_job(client: Arc<Client>, config: &WorkerConfig) -> anyhow::Result<Option<Value>> {
+    Ok(get_jobs(client, config, 1).await?.into_iter().next())
 }
 
-pub fn run_job<F: Fn(Value) -> Value>(handler: F, job: Value) -> Value {
+/// Requests up to `batch_size` jobs from the job-take endpoint in a single call.
+///
+/// The endpoint answers with a single job object or an array of jobs; `204 No Content`,
+/// an empty body or `null` mean no job is available and yield an empty list. A failed request,
+/// an error status or a malformed body is returned as an error.
+pub async fn get_jobs(client: Arc<Client>, config: &WorkerConfig, batch_size: usize) -> anyhow::Result<Vec<Value>> {
+    let job_get_url = match &config.job_get_url {
+        Some(job_get_url) => job_get_url,
+        None => {
+            warn!("RUNPOD_WEBHOOK_GET_JOB not set, switching to get_local");
+            return get_local(config);
+        }
+    };
+
+    let mut request = client.get(job_get_url);
+    if batch_size > 1 {
+        request = request.query(&[("batch_size", batch_size)]);
+    }
+
+    let response = request.send().await.context("job request failed")?;
+    if response.status() == StatusCode::NO_CONTENT {
+        debug!("No job available");
+        return Ok(Vec::new());
+    }
+    if !response.status().is_success() {
+        bail!("job request failed with status {}", response.status());
+    }
+
+    let body = response.text().await.context("could not read job response")?;
+    if body.trim().is_empty() {
+        debug!("No job available");
+        return Ok(Vec::new());
+    }
+
+    let next_jobs = match serde_json::from_str(&body).context("job response is not valid JSON")? {
+        Value::Array(jobs) => jobs,
+        Value::Null => Vec::new(),
+        job => vec![job],
+    };
+
+    for job in &next_jobs {
+        info!("Received job: {:?}", job["id"]);
+    }
+    Ok(next_jobs)
+}
+
+/// Runs `handler` on the job input and shapes its output into a [`JobResult`].
+///
+/// `job["input"]` is deserialized into `T`; if that fails a `ValidationError` is reported
+/// without calling the handler. A handler returning `Err` or panicking is reported as a
+/// [`JobError`], and a job cancelled while running is reported as [`JobResult::Cancelled`].
+/// A handler still running after the job's execution timeout is cancelled and reported as a
+/// `TimeoutError`.
+pub async fn run_job<F, Fut, T, R, E>(
+    client: Arc<Client>,
+    config: Arc<WorkerConfig>,
+    handler: F,
+    job: Value,
+) -> JobResult
+where
+    F: Fn(T, JobContext) -> Fut,
+    Fut: Future<Output = Result<R, E>>,
+    T: DeserializeOwned,
+    R: Serialize,
+    E: Display,
+{
     let start_time = Instant::now();
     info!("Started working on job {:?} at {:?} UTC", job["id"], start_time);
+    install_panic_hook();
 
-    let run_result = handler(job);
-    debug!("Job handler output: {:?}", run_result);
+    let timeout = execution_timeout_for(&job, config.execution_timeout);
+    let context = JobContext::new(&job, client, config.clone());
+    let scratch_dir = context.scratch_dir.clone();
+    let interrupted = interruption(context.cancellation_token.clone(), timeout);
 
-    let mut run_result = if run_result.is_bool() {
-        json!({ "output": run_result })
-    } else if run_result.as_object().unwrap().contains_key("error") {
-        json!({ "error": run_result["error"].to_string() })
-    } else if run_result.as_object().unwrap().contains_key("refresh_worker") {
-        run_result.as_object_mut().unwrap().remove("refresh_worker");
-        json!({ "stopPod": true, "output": run_result })
-    } else {
-        json!({ "output": run_result })
+    let run_result = match parse_input::<T>(&config, &job) {
+        Ok(job_input) => {
+            let handler_call = AssertUnwindSafe(async { handler(job_input, context).await }).catch_unwind();
+            let handler_result = tokio::select! {
+                handler_result = handler_call => Ok(handler_result),
+                interrupted = interrupted => Err(interrupted),
+            };
+
+            match handler_result {
+                Ok(Ok(Ok(output))) => output_result(&config, output, &job),
+                Ok(Ok(Err(err))) => {
+                    error!("Job {:?} handler failed: {}", job["id"], err);
+                    JobError::from_handler_error(&config, &err).into_result()
+                }
+                Ok(Err(panic)) => {
+                    error!("Job {:?} handler panicked", job["id"]);
+                    JobError::from_panic(&config, panic).into_result()
+                }
+                Err(interrupted) => interrupted.into_result(&config, &job),
+            }
+        }
+        Err(validation_error) => validation_error,
     };
+    remove_scratch_dir(&scratch_dir);
 
     check_return_size(&run_result);
 
@@ -81,45 +290,359 @@ pub fn run_job<F: Fn(Value) -> Value>(handler: F, job: Value) -> Value {
     run_result
 }
 
-pub async fn retry_send_result(client: Arc<Client>, job_data: &str) -> Result<(), reqwest::Error> {
-    let config = RetryConfig {
-        max_attempts: 3,
-        base_delay: 1,
-        max_delay: 3,
+/// Runs a streaming `handler` on the job input, posting each partial output to the stream webhook.
+///
+/// The final result is a [`JobResult::Stream`] holding the partial outputs when
+/// `return_aggregate_stream` is set and an empty list otherwise. The first `Err` yielded by the stream, or a panic, ends the job with a
+/// [`JobError`], and cancelling the job or exceeding its execution timeout stops the stream,
+/// including a stream post still in flight.
+pub async fn run_job_stream<F, S, T, R, E>(
+    client: Arc<Client>,
+    config: Arc<WorkerConfig>,
+    handler: F,
+    job: Value,
+    return_aggregate_stream: bool,
+) -> JobResult
+where
+    F: Fn(T, JobContext) -> S,
+    S: Stream<Item = Result<R, E>>,
+    T: DeserializeOwned,
+    R: Serialize,
+    E: Display,
+{
+    let start_time = Instant::now();
+    info!("Started working on streaming job {:?} at {:?} UTC", job["id"], start_time);
+    install_panic_hook();
+
+    let job_input = match parse_input::<T>(&config, &job) {
+        Ok(job_input) => job_input,
+        Err(validation_error) => return validation_error,
+    };
+
+    let timeout = execution_timeout_for(&job, config.execution_timeout);
+    let context = JobContext::new(&job, client.clone(), config.clone());
+    let scratch_dir = context.scratch_dir.clone();
+    let interrupted = interruption(context.cancellation_token.clone(), timeout);
+    futures::pin_mut!(interrupted);
+
+    let mut aggregated_output = Vec::new();
+    let mut run_result = None;
+
+    let stream = match panic::catch_unwind(AssertUnwindSafe(|| handler(job_input, context))) {
+        Ok(stream) => AssertUnwindSafe(stream).catch_unwind(),
+        Err(panic) => {
+            error!("Job {:?} handler panicked", job["id"]);
+            remove_scratch_dir(&scratch_dir);
+            return JobError::from_panic(&config, panic).into_result();
+        }
+    };
+    futures::pin_mut!(stream);
+    loop {
+        let partial = tokio::select! {
+            partial = stream.next() => partial,
+            interrupted = &mut interrupted => {
+                run_result = Some(interrupted.into_result(&config, &job));
+                break;
+            }
+        };
+        let partial = match partial {
+            Some(Ok(partial)) => partial,
+            Some(Err(panic)) => {
+                error!("Job {:?} handler panicked", job["id"]);
+                run_result = Some(JobError::from_panic(&config, panic).into_result());
+                break;
+            }
+            None => break,
+        };
+
+        let partial = match partial.map(serde_json::to_value) {
+            Ok(Ok(partial)) => partial,
+            Ok(Err(err)) => {
+                error!("Job {:?} partial output could not be serialized: {}", job["id"], err);
+                run_result = Some(serialization_error(&config, &err));
+                break;
+            }
+            Err(err) => {
+                error!("Job {:?} handler failed: {}", job["id"], err);
+                run_result = Some(JobError::from_handler_error(&config, &err).into_result());
+                break;
+            }
+        };
+
+        debug!("Job handler partial output: {:?}", partial);
+        if return_aggregate_stream {
+            aggregated_output.push(partial.clone());
+        }
+        // Posting the partial output counts towards the job's execution time too.
+        let partial = JobResult::Output(partial);
+        tokio::select! {
+            _ = stream_result(client.clone(), &config, &partial, &job) => {}
+            interrupted = &mut interrupted => {
+                run_result = Some(interrupted.into_result(&config, &job));
+                break;
+            }
+        }
+    }
+
+    remove_scratch_dir(&scratch_dir);
+
+    let run_result = run_result.unwrap_or(JobResult::Stream(aggregated_output));
+    check_return_size(&run_result);
+
+    let end_time = Instant::now();
+    info!("Finished working on streaming job {:?} at {:?} UTC", job["id"], end_time);
+    info!("Job took {:?} seconds to complete", end_time.duration_since(start_time));
+    debug!("Run result: {:?}", run_result);
+
+    run_result
+}
+
+/// Why a job was stopped before its handler finished.
+enum Interrupted {
+    Cancelled,
+    TimedOut(Duration),
+}
+
+impl Interrupted {
+    fn into_result(self, config: &WorkerConfig, job: &Value) -> JobResult {
+        match self {
+            Interrupted::Cancelled => {
+                info!("Job {:?} was cancelled", job["id"]);
+                JobResult::Cancelled
+            }
+            Interrupted::TimedOut(timeout) => {
+                error!("Job {:?} timed out after {:?}", job["id"], timeout);
+                let error_message = format!("Job exceeded its execution timeout of {:?}", timeout);
+                JobError::new(config, "TimeoutError", error_message).into_result()
+            }
+        }
+    }
+}
+
+/// Resolves when the job is cancelled or its execution timeout elapses, cancelling the job's
+/// token in the latter case so blocking handler work can stop too.
+async fn interruption(cancellation_token: CancellationToken, timeout: Option<Duration>) -> Interrupted {
+    let timed_out = async {
+        match timeout {
+            Some(timeout) => sleep(timeout).await,
+            None => future::pending().await,
+        }
     };
-    retry(config, || async {
-        let resp = client
-            .post(get_done_url().as_str())
-            .header("charset", "utf-8")
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(job_data.to_string())
-            .send()
-            .await?;
 
-        if resp.status() != StatusCode::OK {
-            return Err(reqwest::Error::Status(resp.status()));
+    tokio::select! {
+        _ = cancellation_token.cancelled() => Interrupted::Cancelled,
+        _ = timed_out => {
+            cancellation_token.cancel();
+            Interrupted::TimedOut(timeout.unwrap_or_default())
+        }
+    }
+}
+
+/// Execution timeout of `job`, taken from its `policy.executionTimeout` (ms) and falling
+/// back to the worker-wide `default_timeout`.
+fn execution_timeout_for(job: &Value, default_timeout: Option<Duration>) -> Option<Duration> {
+    job["policy"]["executionTimeout"]
+        .as_u64()
+        .filter(|timeout| *timeout > 0)
+        .map(Duration::from_millis)
+        .or(default_timeout)
+}
+
+/// Deserializes `job["input"]`, returning the `ValidationError` result on failure.
+fn parse_input<T: DeserializeOwned>(config: &WorkerConfig, job: &Value) -> Result<T, JobResult> {
+    serde_json::from_value::<T>(job["input"].clone()).map_err(|err| {
+        error!("Job {:?} input failed validation: {}", job["id"], err);
+        JobError::new(config, "ValidationError", err.to_string()).into_result()
+    })
+}
+
+fn output_result<R: Serialize>(config: &WorkerConfig, output: R, job: &Value) -> JobResult {
+    match serde_json::to_value(output) {
+        Ok(output) => {
+            debug!("Job handler output: {:?}", output);
+            JobResult::from_output(output)
+        }
+        Err(err) => {
+            error!("Job {:?} output could not be serialized: {}", job["id"], err);
+            serialization_error(config, &err)
+        }
+    }
+}
+
+fn serialization_error(config: &WorkerConfig, err: &serde_json::Error) -> JobResult {
+    let error_message = format!("Handler output could not be serialized: {}", err);
+    JobError::new(config, "SerializationError", error_message).into_result()
+}
+
+pub async fn retry_send_result(
+    client: Arc<Client>,
+    config: &WorkerConfig,
+    job_id: &str,
+    job_data: &str,
+) -> Result<(), reqwest::Error> {
+    transmit(client, config, &config.done_url(job_id), job_data).await
+}
+
+/// Posts the JSON `job_data` to `url`, retrying up to three times with backoff.
+///
+/// Bodies larger than the configured `gzip_threshold` are sent gzip-compressed.
+pub async fn transmit(
+    client: Arc<Client>,
+    config: &WorkerConfig,
+    url: &str,
+    job_data: &str,
+) -> Result<(), reqwest::Error> {
+    let (body, compressed) = encode_body(job_data, config.gzip_threshold);
+
+    retry(|| async {
+        let mut request = client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if compressed {
+            request = request.header(CONTENT_ENCODING, "gzip");
         }
 
+        let resp = request.send().await?.error_for_status()?;
+
         debug!("Result API response: {:?}", resp.text().await);
         Ok(())
-    })
+    }, 3, Duration::from_secs(1), Duration::from_secs(3))
     .await
 }
 
-pub async fn send_result(client: Arc<Client>, mut job_data: Value, job: &Value) {
-    if let Err(err) = serde_json::to_string(&job_data) {
-        error!("Error while serializing job result {:?}: {:?}", job["id"], err);
-        return;
+/// Gzip-compresses `job_data` when it exceeds the threshold, falling back to the plain body if
+/// compression fails.
+fn encode_body(job_data: &str, gzip_threshold: usize) -> (Vec<u8>, bool) {
+    if job_data.len() <= gzip_threshold {
+        return (job_data.as_bytes().to_vec(), false);
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    match encoder.write_all(job_data.as_bytes()).and_then(|_| encoder.finish()) {
+        Ok(compressed) => {
+            debug!("Compressed job data from {} to {} bytes", job_data.len(), compressed.len());
+            (compressed, true)
+        }
+        Err(err) => {
+            warn!("Error while compressing job data, sending it uncompressed: {:?}", err);
+            (job_data.as_bytes().to_vec(), false)
+        }
     }
+}
+
+pub async fn send_result(client: Arc<Client>, config: &WorkerConfig, job_result: &JobResult, job: &Value) {
+    let job_data = match serde_json::to_string(job_result) {
+        Ok(job_data) => job_data,
+        Err(err) => {
+            error!("Error while serializing job result {:?}: {:?}", job["id"], err);
+            return;
+        }
+    };
 
-    if JOB_GET_URL.is_some() {
-        info!("Sending job results for {:?}: {:?}", job["id"], job_data);
-        if let Err(err) = retry_send_result(client.clone(), &serde_json::to_string(&job_data).unwrap()).await {
+    if !config.is_local_test() {
+        info!("Sending job results for {:?}: {}", job["id"], job_data);
+        if let Err(err) = retry_send_result(client.clone(), config, job_id(job), &job_data).await {
             error!("Error while returning job result {:?}: {:?}", job["id"], err);
+            if !config.uses_outbox() {
+                return;
+            }
+            if let Err(err) = outbox::save(config, job_id(job), &job_data) {
+                error!("Error while saving job result {:?} to the outbox: {:?}", job["id"], err);
+            }
         } else {
             info!("Successfully returned job result {:?}", job["id"]);
         }
     } else {
-        warn!("Local test job results for {:?}: {:?}", job["id"], job_data);
+        let job_data = serde_json::to_string_pretty(job_result).unwrap_or(job_data);
+        println!("Job {} result:\n{}", job_id(job), job_data);
+    }
+}
+
+/// Posts a partial output of a streaming job to the stream webhook.
+///
+/// Partial outputs are dropped, with a single warning, when no stream webhook is configured.
+pub async fn stream_result(client: Arc<Client>, config: &WorkerConfig, job_result: &JobResult, job: &Value) {
+    let job_data = match serde_json::to_string(job_result) {
+        Ok(job_data) => job_data,
+        Err(err) => {
+            error!("Error while serializing stream output {:?}: {:?}", job["id"], err);
+            return;
+        }
+    };
+
+    if !config.is_local_test() {
+        if config.job_stream_url.is_empty() {
+            static MISSING_STREAM_URL: Once = Once::new();
+            MISSING_STREAM_URL.call_once(|| warn!("RUNPOD_WEBHOOK_POST_STREAM not set, stream outputs are not posted"));
+            return;
+        }
+        debug!("Sending stream output for {:?}: {}", job["id"], job_data);
+        if let Err(err) = transmit(client, config, &config.stream_url(job_id(job)), &job_data).await {
+            error!("Error while streaming job output {:?}: {:?}", job["id"], err);
+        }
+    } else {
+        info!("Local test stream output for {:?}: {}", job["id"], job_data);
+    }
+}
+
+fn job_id(job: &Value) -> &str {
+    job["id"].as_str().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_expectation_mode() {
+        let expected = |expected_output: Value| ExpectedOutput::from_job(&json!({ "expected_output": expected_output }));
+
+        assert_eq!(expected(json!({ "subset": { "a": 1 } })), Some(ExpectedOutput::Subset(json!({ "a": 1 }))));
+        assert_eq!(expected(json!({ "schema": {} })), Some(ExpectedOutput::Schema(json!({}))));
+        assert_eq!(expected(json!({ "a": 1 })), Some(ExpectedOutput::Exact(json!({ "a": 1 }))));
+        assert_eq!(expected(json!("text")), Some(ExpectedOutput::Exact(json!("text"))));
+        assert_eq!(ExpectedOutput::from_job(&json!({ "input": {} })), None);
+    }
+
+    #[test]
+    fn exact_reports_every_difference() {
+        let expected = ExpectedOutput::Exact(json!({ "text": "hi", "scores": [1, 2], "meta": { "model": "a" } }));
+
+        assert!(expected.diff(&json!({ "text": "hi", "scores": [1, 2], "meta": { "model": "a" } })).is_empty());
+        assert_eq!(
+            expected.diff(&json!({ "text": "ho", "scores": [1, 3], "meta": {}, "extra": true })),
+            vec![
+                "/meta/model: missing, expected \"a\"",
+                "/scores/1: expected 2, got 3",
+                "/text: expected \"hi\", got \"ho\"",
+                "/extra: unexpected true",
+            ]
+        );
+        assert_eq!(ExpectedOutput::Exact(json!(1)).diff(&json!([1])), vec!["/: expected 1, got [1]"]);
+    }
+
+    #[test]
+    fn subset_allows_additional_keys() {
+        let expected = ExpectedOutput::Subset(json!({ "items": [{ "id": 1 }] }));
+
+        assert!(expected.diff(&json!({ "items": [{ "id": 1, "score": 0.5 }], "took": 3 })).is_empty());
+        assert_eq!(expected.diff(&json!({ "items": [{ "id": 2 }] })), vec!["/items/0/id: expected 1, got 2"]);
+        assert_eq!(expected.diff(&json!({ "items": [] })), vec!["/items: expected [{\"id\":1}], got []"]);
+    }
+
+    #[test]
+    fn schema_reports_validation_errors() {
+        let expected = ExpectedOutput::Schema(json!({
+            "type": "object",
+            "required": ["text"],
+            "properties": { "text": { "type": "string" } },
+        }));
+
+        assert!(expected.diff(&json!({ "text": "hi" })).is_empty());
+        let diffs = expected.diff(&json!({ "text": 1 }));
+        assert_eq!(diffs.len(), 1);
+        assert!(diffs[0].starts_with("/text: "), "{}", diffs[0]);
+        assert_eq!(ExpectedOutput::Schema(json!({ "type": 5 })).diff(&json!(1)).len(), 1);
     }
 }
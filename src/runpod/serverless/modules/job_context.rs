@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use log::warn;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+use super::progress::progress_update;
+use super::worker_config::WorkerConfig;
+use super::worker_state::get_cancellation_token;
+
+/// Directory holding the scratch directories of the jobs.
+pub const JOB_FILES_DIR: &str = "job_files";
+
+/// Per-job information handed to the handler alongside its input.
+#[derive(Clone, Debug)]
+pub struct JobContext {
+    pub job_id: String,
+    /// Id of the worker running the job (`RUNPOD_POD_ID`).
+    pub worker_id: String,
+    /// Time the job waited in the queue before being picked up, in milliseconds.
+    pub delay_time: Option<u64>,
+    /// Number of times the platform has already retried the job.
+    pub retry_count: u64,
+    /// URL the client asked to be called once the job completes.
+    pub webhook: Option<String>,
+    /// Reports intermediate progress to clients polling the job's status.
+    pub progress: ProgressReporter,
+    /// Directory under `job_files/` for the job's temporary files, removed when the job ends.
+    pub scratch_dir: PathBuf,
+    /// Cancelled when the platform cancels the job.
+    ///
+    /// Async handlers are dropped at their next `.await` on cancellation; handlers doing
+    /// blocking or GPU work should check [`JobContext::is_cancelled`] to stop early.
+    pub cancellation_token: CancellationToken,
+}
+
+impl JobContext {
+    pub fn new(job: &Value, client: Arc<Client>, config: Arc<WorkerConfig>) -> Self {
+        let job_id = job["id"].as_str().unwrap_or_default().to_string();
+        let cancellation_token = get_cancellation_token(&job_id).unwrap_or_default();
+
+        let scratch_dir = scratch_dir_for(&job_id);
+        if let Err(err) = fs::create_dir_all(&scratch_dir) {
+            warn!("Could not create scratch directory {:?}: {}", scratch_dir, err);
+        }
+
+        JobContext {
+            worker_id: config.worker_id.clone(),
+            delay_time: job["delayTime"].as_u64(),
+            retry_count: job["retries"].as_u64().unwrap_or(0),
+            webhook: job["webhook"].as_str().map(str::to_string),
+            progress: ProgressReporter {
+                client,
+                config,
+                job_id: job_id.clone(),
+            },
+            scratch_dir,
+            cancellation_token,
+            job_id,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation_token.is_cancelled()
+    }
+}
+
+/// Handle for posting progress updates of a running job.
+#[derive(Clone, Debug)]
+pub struct ProgressReporter {
+    client: Arc<Client>,
+    config: Arc<WorkerConfig>,
+    job_id: String,
+}
+
+impl ProgressReporter {
+    /// Posts `progress` as the job's intermediate output, see [`progress_update`].
+    pub async fn update<P: Serialize>(&self, progress: P) -> bool {
+        progress_update(self.client.clone(), self.config.clone(), &self.job_id, progress).await
+    }
+}
+
+/// Scratch directory of `job_id` under [`JOB_FILES_DIR`], see [`file_name_for`].
+pub fn scratch_dir_for(job_id: &str) -> PathBuf {
+    Path::new(JOB_FILES_DIR).join(file_name_for(job_id))
+}
+
+/// Removes a job's scratch directory, refusing any path that isn't directly under [`JOB_FILES_DIR`].
+pub fn remove_scratch_dir(scratch_dir: &Path) {
+    let mut components = match scratch_dir.strip_prefix(JOB_FILES_DIR) {
+        Ok(relative) => relative.components(),
+        Err(_) => {
+            warn!("Not removing {:?}, it is outside of {}", scratch_dir, JOB_FILES_DIR);
+            return;
+        }
+    };
+    if !matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) {
+        warn!("Not removing {:?}, it is not a job scratch directory", scratch_dir);
+        return;
+    }
+    let _ = fs::remove_dir_all(scratch_dir);
+}
+
+/// `job_id` with every character unsafe in file names replaced, so it names a single entry of
+/// the directory it is joined to.
+pub fn file_name_for(job_id: &str) -> String {
+    let file_name: String = job_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if file_name.is_empty() {
+        "_".to_string()
+    } else {
+        file_name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scratch_dirs_stay_under_job_files() {
+        assert_eq!(scratch_dir_for("abc-123_x"), Path::new("job_files/abc-123_x"));
+        assert_eq!(scratch_dir_for(".."), Path::new("job_files/__"));
+        assert_eq!(scratch_dir_for("/srv/models"), Path::new("job_files/_srv_models"));
+        assert_eq!(scratch_dir_for(""), Path::new("job_files/_"));
+    }
+
+    #[test]
+    fn refuses_to_remove_other_directories() {
+        let outside = std::env::temp_dir().join(format!("runpod-scratch-test-{}", std::process::id()));
+        fs::create_dir_all(&outside).unwrap();
+        remove_scratch_dir(&outside);
+        remove_scratch_dir(Path::new("job_files/../job_files"));
+        remove_scratch_dir(Path::new(JOB_FILES_DIR));
+        assert!(outside.exists());
+        fs::remove_dir_all(&outside).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use std::any::{type_name, Any};
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::panic;
+use std::sync::Once;
+
+use serde::Serialize;
+
+use super::job_result::JobResult;
+use super::worker_config::WorkerConfig;
+
+thread_local! {
+    static PANIC_BACKTRACE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static PANIC_HOOK: Once = Once::new();
+
+/// Error reported to the platform when a job fails.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobError {
+    pub error_type: String,
+    pub error_message: String,
+    /// Backtrace of a handler panic; errors returned by the handler carry none.
+    pub error_traceback: Option<String>,
+    pub hostname: String,
+    pub worker_id: String,
+    pub runpod_version: String,
+}
+
+impl JobError {
+    /// Error of a job run by the worker configured by `config`.
+    pub fn new(config: &WorkerConfig, error_type: impl Into<String>, error_message: impl Into<String>) -> Self {
+        JobError {
+            error_type: error_type.into(),
+            error_message: error_message.into(),
+            error_traceback: None,
+            hostname: hostname(),
+            worker_id: config.worker_id.clone(),
+            runpod_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Error for a handler returning `Err(err)`, typed after the handler's error type.
+    pub fn from_handler_error<E: Display>(config: &WorkerConfig, err: &E) -> Self {
+        JobError::new(config, short_type_name::<E>(), err.to_string())
+    }
+
+    /// Error for a handler panic, from the payload caught by `catch_unwind`.
+    pub fn from_panic(config: &WorkerConfig, payload: Box<dyn Any + Send>) -> Self {
+        let error_message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "Handler panicked".to_string(),
+            },
+        };
+
+        let mut job_error = JobError::new(config, "Panic", error_message);
+        job_error.error_traceback = PANIC_BACKTRACE.with(|backtrace| backtrace.borrow_mut().take());
+        job_error
+    }
+
+    /// The job result reporting this error, with the error serialized as a JSON string.
+    pub fn into_result(self) -> JobResult {
+        let error = serde_json::to_string(&self).unwrap_or(self.error_message);
+        JobResult::Error(error)
+    }
+}
+
+/// Records the backtrace of every panic so [`JobError::from_panic`] can report it.
+///
+/// Installed once per process, keeping the previously installed hook.
+pub fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let backtrace = Backtrace::force_capture().to_string();
+            PANIC_BACKTRACE.with(|last_backtrace| *last_backtrace.borrow_mut() = Some(backtrace));
+            previous_hook(info);
+        }));
+    });
+}
+
+fn hostname() -> String {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok().map(|hostname| hostname.trim().to_string()))
+        .unwrap_or_default()
+}
+
+/// Type name without its module path, e.g. `Error` for `anyhow::Error`.
+fn short_type_name<T>() -> &'static str {
+    let name = type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_configured_worker_id() {
+        let config = WorkerConfig { worker_id: "worker-7".to_string(), ..WorkerConfig::default() };
+        let job_error = JobError::from_handler_error(&config, &"boom".to_string());
+
+        assert_eq!(job_error.worker_id, "worker-7");
+        assert_eq!(job_error.error_type, "String");
+        assert_eq!(job_error.error_message, "boom");
+    }
+}
@@ -0,0 +1,152 @@
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Final result of a job, serialized in the shape the job-done webhook expects.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobResult {
+    /// `{"output": <output>}`; any JSON value is accepted.
+    Output(Value),
+    /// `{"error": "<message>"}`, where the message is usually a serialized `JobError`.
+    Error(String),
+    /// The wrapped result with `"stopPod": true`, asking the platform to refresh the worker.
+    StopPod(Box<JobResult>),
+    /// `{"output": [<partial>, ...]}` holding the aggregated outputs of a streaming job.
+    Stream(Vec<Value>),
+    /// `{"status": "CANCELLED"}` for a job cancelled while running.
+    Cancelled,
+}
+
+impl JobResult {
+    /// Shapes a handler output, treating an object with a truthy `error` key as a failure and
+    /// one with a truthy `refresh_worker` key as a refresh request. Both keys are removed from
+    /// the output; `null`, `false`, `0` and empty values don't count, as in runpod-python.
+    pub fn from_output(mut output: Value) -> Self {
+        let (error, refresh_worker) = match output.as_object_mut() {
+            Some(fields) => (
+                fields.remove("error").filter(is_truthy),
+                fields.remove("refresh_worker").as_ref().is_some_and(is_truthy),
+            ),
+            None => (None, false),
+        };
+
+        let result = match error {
+            Some(Value::String(error)) => JobResult::Error(error),
+            Some(error) => JobResult::Error(error.to_string()),
+            None => JobResult::Output(output),
+        };
+        if refresh_worker {
+            result.with_stop_pod()
+        } else {
+            result
+        }
+    }
+
+    /// Marks the result as requesting a worker refresh.
+    pub fn with_stop_pod(self) -> Self {
+        match self {
+            JobResult::StopPod(_) => self,
+            result => JobResult::StopPod(Box::new(result)),
+        }
+    }
+
+    /// Output produced by the job, `None` when it failed or was cancelled.
+    pub fn output(&self) -> Option<Value> {
+        match self {
+            JobResult::Output(output) => Some(output.clone()),
+            JobResult::Stream(outputs) => Some(Value::Array(outputs.clone())),
+            JobResult::StopPod(result) => result.output(),
+            JobResult::Error(_) | JobResult::Cancelled => None,
+        }
+    }
+
+    pub fn is_stop_pod(&self) -> bool {
+        matches!(self, JobResult::StopPod(_))
+    }
+
+    pub fn is_error(&self) -> bool {
+        match self {
+            JobResult::Error(_) => true,
+            JobResult::StopPod(result) => result.is_error(),
+            _ => false,
+        }
+    }
+
+    fn serialize_entries<M: SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+        match self {
+            JobResult::Output(output) => map.serialize_entry("output", output),
+            JobResult::Error(error) => map.serialize_entry("error", error),
+            JobResult::StopPod(result) => {
+                result.serialize_entries(map)?;
+                map.serialize_entry("stopPod", &true)
+            }
+            JobResult::Stream(outputs) => map.serialize_entry("output", outputs),
+            JobResult::Cancelled => map.serialize_entry("status", "CANCELLED"),
+        }
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(value) => *value,
+        Value::Number(value) => value.as_f64() != Some(0.0),
+        Value::String(value) => !value.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        Value::Object(fields) => !fields.is_empty(),
+    }
+}
+
+impl Serialize for JobResult {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        self.serialize_entries(&mut map)?;
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serialized(result: &JobResult) -> Value {
+        serde_json::to_value(result).unwrap()
+    }
+
+    #[test]
+    fn serializes_each_variant() {
+        assert_eq!(serialized(&JobResult::Output(json!([1, 2]))), json!({ "output": [1, 2] }));
+        assert_eq!(serialized(&JobResult::Error("boom".to_string())), json!({ "error": "boom" }));
+        assert_eq!(serialized(&JobResult::Stream(vec![json!("a")])), json!({ "output": ["a"] }));
+        assert_eq!(serialized(&JobResult::Cancelled), json!({ "status": "CANCELLED" }));
+        assert_eq!(
+            serialized(&JobResult::Output(json!(1)).with_stop_pod().with_stop_pod()),
+            json!({ "output": 1, "stopPod": true })
+        );
+    }
+
+    #[test]
+    fn truthy_error_key_fails_the_job() {
+        assert_eq!(JobResult::from_output(json!({ "error": "bad input" })), JobResult::Error("bad input".to_string()));
+        assert_eq!(
+            JobResult::from_output(json!({ "error": { "code": 3 } })),
+            JobResult::Error("{\"code\":3}".to_string())
+        );
+    }
+
+    #[test]
+    fn falsy_error_key_is_dropped_from_the_output() {
+        for error in [json!(null), json!(""), json!(false), json!(0), json!([])] {
+            let result = JobResult::from_output(json!({ "error": error, "text": "ok" }));
+            assert_eq!(result, JobResult::Output(json!({ "text": "ok" })));
+        }
+    }
+
+    #[test]
+    fn refresh_worker_requests_stop_pod() {
+        let result = JobResult::from_output(json!({ "text": "ok", "refresh_worker": true }));
+        assert_eq!(serialized(&result), json!({ "output": { "text": "ok" }, "stopPod": true }));
+        assert!(!JobResult::from_output(json!({ "refresh_worker": false })).is_stop_pod());
+        assert!(JobResult::from_output(json!({ "error": "x", "refresh_worker": true })).is_error());
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::time::timeout_at;
+use uuid::Uuid;
+
+/// Status of a job held by the local API, as reported by `/status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum JobStatus {
+    InQueue,
+    InProgress,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job is done and its status won't change anymore.
+    pub fn is_final(self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+struct StoredJob {
+    job: Value,
+    status: JobStatus,
+    output: Option<Value>,
+    error: Option<String>,
+    /// Partial outputs not yet returned by `/stream`.
+    stream: Vec<Value>,
+    /// Cancelled while running, and the worker hasn't been told yet.
+    cancel_pending: bool,
+    /// Concurrency slot held while the worker runs the job.
+    slot: Option<OwnedSemaphorePermit>,
+    submitted_at: Instant,
+    started_at: Option<Instant>,
+    finished_at: Option<Instant>,
+}
+
+#[derive(Default)]
+struct StoreState {
+    jobs: HashMap<String, StoredJob>,
+    queue: VecDeque<String>,
+}
+
+/// In-memory store of the jobs submitted to the local API.
+///
+/// At most `concurrency` jobs are handed to the worker at once; the others wait in the queue.
+#[derive(Clone)]
+pub struct JobStore {
+    state: Arc<Mutex<StoreState>>,
+    changed: Arc<Notify>,
+    slots: Arc<Semaphore>,
+    concurrency: usize,
+}
+
+impl JobStore {
+    pub fn new(concurrency: usize) -> Self {
+        let concurrency = concurrency.max(1);
+        JobStore {
+            state: Arc::default(),
+            changed: Arc::default(),
+            slots: Arc::new(Semaphore::new(concurrency)),
+            concurrency,
+        }
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// Queues the job described by a `/run` request body, returning its id.
+    pub fn submit(&self, request: Value) -> String {
+        let id = Uuid::new_v4().to_string();
+        let mut job = json!({ "id": id, "input": request["input"] });
+        for key in ["webhook", "policy"] {
+            if !request[key].is_null() {
+                job[key] = request[key].clone();
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.jobs.insert(
+            id.clone(),
+            StoredJob {
+                job,
+                status: JobStatus::InQueue,
+                output: None,
+                error: None,
+                stream: Vec::new(),
+                cancel_pending: false,
+                slot: None,
+                submitted_at: Instant::now(),
+                started_at: None,
+                finished_at: None,
+            },
+        );
+        state.queue.push_back(id.clone());
+        drop(state);
+
+        self.changed.notify_waiters();
+        id
+    }
+
+    /// Takes up to `count` queued jobs, marking them `IN_PROGRESS`, as long as concurrency slots
+    /// are free.
+    pub fn take(&self, count: usize) -> Vec<Value> {
+        let mut state = self.state.lock().unwrap();
+        let mut jobs = Vec::new();
+        while jobs.len() < count && !state.queue.is_empty() {
+            let slot = match self.slots.clone().try_acquire_owned() {
+                Ok(slot) => slot,
+                Err(_) => break,
+            };
+            let id = match state.queue.pop_front() {
+                Some(id) => id,
+                None => break,
+            };
+            if let Some(stored) = state.jobs.get_mut(&id) {
+                stored.slot = Some(slot);
+                stored.status = JobStatus::InProgress;
+                stored.started_at = Some(Instant::now());
+                jobs.push(stored.job.clone());
+            }
+        }
+        jobs
+    }
+
+    /// Same as [`JobStore::take`], waiting up to `timeout` for a job to be queued and a slot to
+    /// be free.
+    pub async fn take_wait(&self, count: usize, timeout: Duration) -> Vec<Value> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let jobs = self.take(count);
+            if !jobs.is_empty() || timeout_at(deadline, changed).await.is_err() {
+                return jobs;
+            }
+        }
+    }
+
+    /// Body of the `/status` response for job `id`.
+    pub fn status(&self, id: &str) -> Option<Value> {
+        let state = self.state.lock().unwrap();
+        state.jobs.get(id).map(|stored| status_body(id, stored))
+    }
+
+    /// Waits up to `timeout` for job `id` to finish, returning its status either way.
+    pub async fn wait_until_final(&self, id: &str, timeout: Duration) -> Option<Value> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+
+            let (status, is_final) = {
+                let state = self.state.lock().unwrap();
+                let stored = state.jobs.get(id)?;
+                (status_body(id, stored), stored.status.is_final())
+            };
+            if is_final || timeout_at(deadline, changed).await.is_err() {
+                return Some(status);
+            }
+        }
+    }
+
+    /// Body of the `/stream` response for job `id`, holding the partial outputs produced since
+    /// the previous call.
+    pub fn drain_stream(&self, id: &str) -> Option<Value> {
+        let mut state = self.state.lock().unwrap();
+        let stored = state.jobs.get_mut(id)?;
+        let stream: Vec<Value> = stored.stream.drain(..).map(|output| json!({ "output": output })).collect();
+        Some(json!({ "id": id, "status": stored.status, "stream": stream }))
+    }
+
+    /// Cancels job `id`, returning its new status.
+    ///
+    /// A queued job is dropped right away; a running one is cancelled on the worker's next
+    /// heartbeat, see [`JobStore::take_cancelled`].
+    pub fn cancel(&self, id: &str) -> Option<JobStatus> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let stored = state.jobs.get_mut(id)?;
+        match stored.status {
+            JobStatus::InQueue => {
+                stored.status = JobStatus::Cancelled;
+                stored.finished_at = Some(Instant::now());
+                state.queue.retain(|queued_id| queued_id != id);
+            }
+            JobStatus::InProgress => {
+                stored.status = JobStatus::Cancelled;
+                stored.finished_at = Some(Instant::now());
+                stored.cancel_pending = true;
+            }
+            _ => {}
+        }
+        let status = stored.status;
+        drop(guard);
+
+        self.changed.notify_waiters();
+        Some(status)
+    }
+
+    /// Ids among `job_ids` that were cancelled since the worker was last told, as returned in
+    /// the heartbeat response.
+    pub fn take_cancelled(&self, job_ids: &[&str]) -> Vec<String> {
+        let mut state = self.state.lock().unwrap();
+        job_ids
+            .iter()
+            .filter_map(|id| state.jobs.get_mut(*id).filter(|stored| stored.cancel_pending).map(|stored| {
+                stored.cancel_pending = false;
+                id.to_string()
+            }))
+            .collect()
+    }
+
+    /// Records a payload posted by the worker for job `id`: a progress update while the job
+    /// runs, or its final result, which frees the job's concurrency slot.
+    pub fn record_result(&self, id: &str, result: &Value) {
+        let mut state = self.state.lock().unwrap();
+        let stored = match state.jobs.get_mut(id) {
+            Some(stored) => stored,
+            None => return,
+        };
+
+        if result["status"] == "IN_PROGRESS" {
+            if !stored.status.is_final() {
+                stored.output = Some(result["output"].clone());
+            }
+        } else {
+            stored.slot = None;
+            // A job cancelled while running keeps its status once the worker stops it.
+            if !stored.status.is_final() {
+                stored.status = if result["status"] == "CANCELLED" {
+                    JobStatus::Cancelled
+                } else if let Some(error) = result.get("error") {
+                    stored.error = Some(error.as_str().map_or_else(|| error.to_string(), str::to_string));
+                    JobStatus::Failed
+                } else {
+                    stored.output = Some(result["output"].clone());
+                    JobStatus::Completed
+                };
+                stored.finished_at = Some(Instant::now());
+            }
+        }
+        drop(state);
+
+        self.changed.notify_waiters();
+    }
+
+    /// Records a partial output posted by the worker for streaming job `id`.
+    pub fn record_stream(&self, id: &str, result: &Value) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(stored) = state.jobs.get_mut(id) {
+            stored.stream.push(result["output"].clone());
+        }
+    }
+
+    /// Number of jobs in each status.
+    pub fn counts(&self) -> HashMap<JobStatus, usize> {
+        let mut counts = HashMap::new();
+        for stored in self.state.lock().unwrap().jobs.values() {
+            *counts.entry(stored.status).or_default() += 1;
+        }
+        counts
+    }
+}
+
+fn status_body(id: &str, stored: &StoredJob) -> Value {
+    let mut body = json!({ "id": id, "status": stored.status });
+    if let Some(started_at) = stored.started_at {
+        body["delayTime"] = json!(started_at.duration_since(stored.submitted_at).as_millis() as u64);
+        if let Some(finished_at) = stored.finished_at {
+            body["executionTime"] = json!(finished_at.duration_since(started_at).as_millis() as u64);
+        }
+    }
+    if let Some(output) = &stored.output {
+        body["output"] = output.clone();
+    }
+    if let Some(error) = &stored.error {
+        body["error"] = json!(error);
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_of(store: &JobStore, id: &str) -> Value {
+        store.status(id).unwrap()["status"].clone()
+    }
+
+    #[test]
+    fn runs_a_job_through_its_lifecycle() {
+        let store = JobStore::new(1);
+        let id = store.submit(json!({ "input": { "prompt": "hi" }, "webhook": "https://example.com" }));
+        assert_eq!(status_of(&store, &id), "IN_QUEUE");
+
+        let jobs = store.take(5);
+        assert_eq!(jobs, vec![json!({ "id": id, "input": { "prompt": "hi" }, "webhook": "https://example.com" })]);
+        assert_eq!(status_of(&store, &id), "IN_PROGRESS");
+
+        store.record_result(&id, &json!({ "status": "IN_PROGRESS", "output": "50%" }));
+        assert_eq!(store.status(&id).unwrap()["output"], "50%");
+
+        store.record_result(&id, &json!({ "output": { "text": "done" } }));
+        let status = store.status(&id).unwrap();
+        assert_eq!(status["status"], "COMPLETED");
+        assert_eq!(status["output"], json!({ "text": "done" }));
+        assert!(status["executionTime"].is_u64());
+        assert_eq!(store.status("unknown"), None);
+    }
+
+    #[test]
+    fn limits_running_jobs_to_the_concurrency() {
+        let store = JobStore::new(2);
+        let ids: Vec<String> = (0..3).map(|_| store.submit(json!({ "input": {} }))).collect();
+
+        assert_eq!(store.take(5).len(), 2);
+        assert!(store.take(1).is_empty());
+        assert_eq!(store.counts()[&JobStatus::InQueue], 1);
+
+        store.record_result(&ids[0], &json!({ "error": "boom" }));
+        assert_eq!(status_of(&store, &ids[0]), "FAILED");
+        assert_eq!(store.status(&ids[0]).unwrap()["error"], "boom");
+        assert_eq!(store.take(5), vec![json!({ "id": ids[2], "input": {} })]);
+    }
+
+    #[test]
+    fn cancels_queued_and_running_jobs() {
+        let store = JobStore::new(1);
+        let running = store.submit(json!({ "input": 1 }));
+        let queued = store.submit(json!({ "input": 2 }));
+        store.take(1);
+
+        assert_eq!(store.cancel(&queued), Some(JobStatus::Cancelled));
+        assert_eq!(store.cancel(&running), Some(JobStatus::Cancelled));
+        assert_eq!(store.cancel("unknown"), None);
+
+        assert_eq!(store.take_cancelled(&[running.as_str(), queued.as_str()]), vec![running.clone()]);
+        assert!(store.take_cancelled(&[running.as_str()]).is_empty());
+
+        // The worker's late result neither revives the job nor keeps its slot.
+        store.record_result(&running, &json!({ "output": "late" }));
+        assert_eq!(status_of(&store, &running), "CANCELLED");
+        let next = store.submit(json!({ "input": 3 }));
+        assert_eq!(store.take(1), vec![json!({ "id": next, "input": 3 })]);
+    }
+
+    #[test]
+    fn drains_streamed_outputs() {
+        let store = JobStore::new(1);
+        let id = store.submit(json!({ "input": {} }));
+        store.take(1);
+        store.record_stream(&id, &json!({ "output": "a" }));
+        store.record_stream(&id, &json!({ "output": "b" }));
+
+        let stream = store.drain_stream(&id).unwrap();
+        assert_eq!(stream["stream"], json!([{ "output": "a" }, { "output": "b" }]));
+        assert_eq!(store.drain_stream(&id).unwrap()["stream"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn waits_for_jobs_and_results() {
+        let store = JobStore::new(1);
+        assert!(store.take_wait(1, Duration::from_millis(10)).await.is_empty());
+
+        let id = store.submit(json!({ "input": {} }));
+        let worker = {
+            let store = store.clone();
+            tokio::spawn(async move {
+                let jobs = store.take_wait(1, Duration::from_secs(5)).await;
+                let id = jobs[0]["id"].as_str().unwrap().to_string();
+                store.record_result(&id, &json!({ "output": 42 }));
+            })
+        };
+
+        let status = store.wait_until_final(&id, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(status["status"], "COMPLETED");
+        assert_eq!(status["output"], 42);
+        worker.await.unwrap();
+    }
+}
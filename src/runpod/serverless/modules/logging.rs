@@ -66,9 +66,3 @@ pub fn debug(message: &str) {
 pub fn tip(message: &str) {
     log(message, LogLevel::Tip);
 }
-
-fn main() {
-    log_secret("RUNPOD_AI_API_KEY", env::var("RUNPOD_AI_API_KEY").ok(), LogLevel::Info);
-    log_secret("RUNPOD_WEBHOOK_GET_JOB", env::var("RUNPOD_WEBHOOK_GET_JOB").ok(), LogLevel::Info);
-    log_secret("RUNPOD_WEBHOOK_POST_OUTPUT", env::var("RUNPOD_WEBHOOK_POST_OUTPUT").ok(), LogLevel::Info);
-}
@@ -0,0 +1,18 @@
+pub mod cancellation;
+pub mod heartbeat;
+pub mod http_client;
+pub mod job;
+pub mod job_context;
+pub mod job_error;
+pub mod job_result;
+pub mod job_store;
+pub mod logging;
+pub mod openapi;
+pub mod outbox;
+pub mod progress;
+pub mod retry;
+pub mod rp_fastapi;
+pub mod rp_tips;
+pub mod shutdown;
+pub mod worker_config;
+pub mod worker_state;
@@ -0,0 +1,238 @@
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use log::warn;
+use serde_json::Value;
+
+/// JSON schema of `T` for use as the handler input schema of the local API's OpenAPI document,
+/// with references pointing into `components/schemas`.
+pub fn input_schema_for<T: JsonSchema>() -> Value {
+    let schema = SchemaSettings::openapi3().into_generator().into_root_schema_for::<T>();
+    serde_json::to_value(schema).unwrap_or_else(|_| json!({}))
+}
+
+/// OpenAPI 3 document describing the local API, with `input_schema` as the schema of the job
+/// input when the handler's input type is known.
+///
+/// The schemas of the API itself are prefixed with `Runpod` so they don't replace the
+/// definitions of the input schema, which keep their names.
+pub fn openapi_spec(input_schema: Option<&Value>) -> Value {
+    let mut input_schema = input_schema.cloned().unwrap_or_else(|| json!({ "description": "Handler input." }));
+    let mut schemas = match input_schema.as_object_mut().and_then(|schema| schema.remove("definitions")) {
+        Some(Value::Object(definitions)) => definitions,
+        _ => serde_json::Map::new(),
+    };
+    if let Some(schema) = input_schema.as_object_mut() {
+        schema.remove("$schema");
+    }
+
+    let job_schemas = json!({
+        "RunpodJobInput": input_schema,
+        "RunpodRunRequest": {
+            "type": "object",
+            "required": ["input"],
+            "properties": {
+                "input": { "$ref": "#/components/schemas/RunpodJobInput" },
+                "webhook": { "type": "string", "description": "URL called once the job completes." },
+                "policy": {
+                    "type": "object",
+                    "properties": {
+                        "executionTimeout": { "type": "integer", "description": "Execution timeout in milliseconds." },
+                    },
+                },
+            },
+        },
+        "RunpodJobStatus": {
+            "type": "string",
+            "enum": ["IN_QUEUE", "IN_PROGRESS", "COMPLETED", "FAILED", "CANCELLED"],
+        },
+        "RunpodRunResponse": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "status": { "$ref": "#/components/schemas/RunpodJobStatus" },
+            },
+        },
+        "RunpodStatusResponse": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "status": { "$ref": "#/components/schemas/RunpodJobStatus" },
+                "delayTime": { "type": "integer", "description": "Time spent in the queue in milliseconds." },
+                "executionTime": { "type": "integer", "description": "Time spent running in milliseconds." },
+                "output": { "description": "Handler output, or the latest progress update while in progress." },
+                "error": { "type": "string", "description": "Error reported by a failed job." },
+            },
+        },
+        "RunpodStreamResponse": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "status": { "$ref": "#/components/schemas/RunpodJobStatus" },
+                "stream": {
+                    "type": "array",
+                    "items": { "type": "object", "properties": { "output": {} } },
+                },
+            },
+        },
+        "RunpodHealthResponse": {
+            "type": "object",
+            "properties": {
+                "concurrency": { "type": "integer" },
+                "queueDepth": { "type": "integer" },
+                "jobs": {
+                    "type": "object",
+                    "additionalProperties": { "type": "integer" },
+                },
+            },
+        },
+        "RunpodError": {
+            "type": "object",
+            "properties": { "error": { "type": "string" } },
+        },
+    });
+    if let Value::Object(job_schemas) = job_schemas {
+        for (name, schema) in job_schemas {
+            if schemas.insert(name.clone(), schema).is_some() {
+                warn!("Handler input type {} is shadowed by the local API schema of the same name", name);
+            }
+        }
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "RunPod worker local API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Local stand-in for a serverless endpoint, running jobs on this worker. \
+                Every route is also served under a `/{endpoint_id}` prefix, as on the endpoint API.",
+        },
+        "paths": {
+            "/run": {
+                "post": {
+                    "summary": "Queue a job",
+                    "requestBody": json_body("RunpodRunRequest"),
+                    "responses": {
+                        "200": json_response("Queued job.", "RunpodRunResponse"),
+                        "400": json_response("Invalid request body.", "RunpodError"),
+                    },
+                },
+            },
+            "/runsync": {
+                "post": {
+                    "summary": "Run a job and wait for its result",
+                    "description": "Answers with the job's current status if it takes longer than 90 seconds.",
+                    "requestBody": json_body("RunpodRunRequest"),
+                    "responses": {
+                        "200": json_response("Job status.", "RunpodStatusResponse"),
+                        "400": json_response("Invalid request body.", "RunpodError"),
+                    },
+                },
+            },
+            "/status/{id}": {
+                "get": job_operation("Get the status of a job", "Job status.", "RunpodStatusResponse"),
+            },
+            "/stream/{id}": {
+                "get": job_operation(
+                    "Get the partial outputs a streaming job produced since the last call",
+                    "New partial outputs.",
+                    "RunpodStreamResponse",
+                ),
+            },
+            "/cancel/{id}": {
+                "post": job_operation("Cancel a job", "Cancelled job.", "RunpodRunResponse"),
+            },
+            "/health": {
+                "get": {
+                    "summary": "Get job counts and queue depth",
+                    "responses": { "200": json_response("Server health.", "RunpodHealthResponse") },
+                },
+            },
+        },
+        "components": { "schemas": schemas },
+    })
+}
+
+/// Page rendering the OpenAPI document at `spec_url` with Swagger UI.
+pub fn docs_page(spec_url: &str) -> String {
+    format!(
+        r##"<!DOCTYPE html>
+<html>
+<head>
+  <title>RunPod worker local API</title>
+  <meta charset="utf-8">
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>SwaggerUIBundle({{ url: "{}", dom_id: "#swagger-ui" }});</script>
+</body>
+</html>
+"##,
+        spec_url
+    )
+}
+
+fn job_operation(summary: &str, description: &str, schema: &str) -> Value {
+    json!({
+        "summary": summary,
+        "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+        "responses": {
+            "200": json_response(description, schema),
+            "404": json_response("Unknown job.", "RunpodError"),
+        },
+    })
+}
+
+fn json_body(schema: &str) -> Value {
+    json!({
+        "required": true,
+        "content": { "application/json": { "schema": { "$ref": format!("#/components/schemas/{}", schema) } } },
+    })
+}
+
+fn json_response(description: &str, schema: &str) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": { "$ref": format!("#/components/schemas/{}", schema) } } },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Error {
+        code: u32,
+    }
+
+    #[allow(dead_code)]
+    #[derive(JsonSchema)]
+    struct Input {
+        prompt: String,
+        error: Option<Error>,
+    }
+
+    #[test]
+    fn keeps_input_definitions_next_to_api_schemas() {
+        let spec = openapi_spec(Some(&input_schema_for::<Input>()));
+        let schemas = &spec["components"]["schemas"];
+
+        assert_eq!(schemas["Error"]["required"], json!(["code"]));
+        assert_eq!(schemas["RunpodError"]["properties"]["error"]["type"], "string");
+        assert_eq!(schemas["RunpodJobInput"]["title"], "Input");
+        assert!(schemas["RunpodJobInput"].get("definitions").is_none());
+        assert!(schemas["RunpodJobInput"].get("$schema").is_none());
+        assert!(spec.to_string().contains("\"#/components/schemas/Error\""));
+    }
+
+    #[test]
+    fn accepts_any_input_without_a_schema() {
+        let spec = openapi_spec(None);
+        assert_eq!(spec["components"]["schemas"]["RunpodJobInput"], json!({ "description": "Handler input." }));
+        let run_body = &spec["paths"]["/run"]["post"]["requestBody"]["content"]["application/json"];
+        assert_eq!(run_body["schema"]["$ref"], "#/components/schemas/RunpodRunRequest");
+    }
+}
@@ -0,0 +1,104 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+use super::job::retry_send_result;
+use super::job_context::file_name_for;
+use super::worker_config::WorkerConfig;
+
+/// Time between two attempts at delivering the results left in the outbox.
+pub const OUTBOX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A job result that could not be posted, as stored in the outbox.
+#[derive(Debug, Serialize, Deserialize)]
+struct OutboxEntry {
+    id: String,
+    result: Value,
+}
+
+/// Writes the result of `job_id` to the outbox so it is posted again later.
+pub fn save(config: &WorkerConfig, job_id: &str, job_data: &str) -> io::Result<()> {
+    let result = serde_json::from_str(job_data)?;
+    let entry = OutboxEntry { id: job_id.to_string(), result };
+
+    fs::create_dir_all(&config.outbox_dir)?;
+    fs::write(entry_path(&config.outbox_dir, job_id), serde_json::to_vec(&entry)?)?;
+    info!("Saved result of job {} to the outbox", job_id);
+    Ok(())
+}
+
+/// Tries to post every result in the outbox, removing the ones that were delivered.
+///
+/// Returns the number of results still waiting in the outbox.
+pub async fn flush(client: Arc<Client>, config: &WorkerConfig) -> usize {
+    let entries = match fs::read_dir(&config.outbox_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return 0,
+        Err(err) => {
+            error!("Error while reading the outbox: {:?}", err);
+            return 0;
+        }
+    };
+
+    let mut pending = 0;
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        if path.extension() != Some(OsStr::new("json")) {
+            continue;
+        }
+
+        let entry = match read_entry(&path) {
+            Ok(entry) => entry,
+            Err(err) => {
+                error!("Skipping unreadable outbox entry {:?}: {:?}", path, err);
+                continue;
+            }
+        };
+
+        match retry_send_result(client.clone(), config, &entry.id, &entry.result.to_string()).await {
+            Ok(()) => {
+                info!("Delivered result of job {} from the outbox", entry.id);
+                if let Err(err) = fs::remove_file(&path) {
+                    error!("Error while removing outbox entry {:?}: {:?}", path, err);
+                }
+            }
+            Err(err) => {
+                warn!("Result of job {} is still undelivered: {:?}", entry.id, err);
+                pending += 1;
+            }
+        }
+    }
+    pending
+}
+
+/// Starts a background task delivering the outbox right away and then every
+/// [`OUTBOX_RETRY_INTERVAL`], so results left over by a previous run are retried on startup.
+pub fn start_retry_loop(client: Arc<Client>, config: Arc<WorkerConfig>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let pending = flush(client.clone(), &config).await;
+            if pending > 0 {
+                debug!("{} results waiting in the outbox", pending);
+            }
+            sleep(OUTBOX_RETRY_INTERVAL).await;
+        }
+    })
+}
+
+fn read_entry(path: &Path) -> anyhow::Result<OutboxEntry> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// File of `job_id` in the outbox, with characters unsafe in file names replaced.
+fn entry_path(dir: &Path, job_id: &str) -> PathBuf {
+    dir.join(format!("{}.json", file_name_for(job_id)))
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, error, info};
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::time::sleep;
+
+use super::job::transmit;
+use super::worker_config::WorkerConfig;
+use super::worker_state::{schedule_progress_update, take_pending_progress, ProgressSchedule};
+
+/// Minimum time between two progress updates of the same job; faster updates are held back.
+pub const PROGRESS_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Posts `progress` as the intermediate output of a running job, visible to clients polling
+/// its status while it is `IN_PROGRESS`.
+///
+/// Updates are retried on failure. An update sent less than [`PROGRESS_MIN_INTERVAL`] after the
+/// previous one is held back and posted once the interval has passed, unless a newer update
+/// replaces it first or the handler finishes. Returns whether the update was delivered or held
+/// back.
+pub async fn progress_update<P: Serialize>(
+    client: Arc<Client>,
+    config: Arc<WorkerConfig>,
+    job_id: &str,
+    progress: P,
+) -> bool {
+    let progress = match serde_json::to_value(progress) {
+        Ok(progress) => progress,
+        Err(err) => {
+            error!("Progress update for job {} could not be serialized: {}", job_id, err);
+            return false;
+        }
+    };
+
+    match schedule_progress_update(job_id, &progress, PROGRESS_MIN_INTERVAL) {
+        ProgressSchedule::Now => send_progress(client, &config, job_id, progress).await,
+        ProgressSchedule::After(delay) => {
+            debug!("Holding back progress update for job {} for {:?}", job_id, delay);
+            let job_id = job_id.to_string();
+            tokio::spawn(async move {
+                sleep(delay).await;
+                if let Some(progress) = take_pending_progress(&job_id) {
+                    send_progress(client, &config, &job_id, progress).await;
+                }
+            });
+            true
+        }
+        ProgressSchedule::Coalesced => {
+            debug!("Replaced the held back progress update for job {}", job_id);
+            true
+        }
+        ProgressSchedule::Closed => {
+            debug!("Dropping progress update for job {}, its handler has finished", job_id);
+            false
+        }
+    }
+}
+
+async fn send_progress(client: Arc<Client>, config: &WorkerConfig, job_id: &str, progress: Value) -> bool {
+    let progress_data = json!({ "status": "IN_PROGRESS", "output": progress });
+    if config.is_local_test() {
+        info!("Local test progress for job {}: {}", job_id, progress_data);
+        return true;
+    }
+
+    match transmit(client, config, &config.progress_url(job_id), &progress_data.to_string()).await {
+        Ok(()) => {
+            debug!("Sent progress update for job {}: {}", job_id, progress_data);
+            true
+        }
+        Err(err) => {
+            error!("Error while sending progress update for job {}: {:?}", job_id, err);
+            false
+        }
+    }
+}
@@ -1,87 +1,248 @@
-use std::env;
-use std::sync::{Arc, Mutex};
-use tokio::task;
-use uuid::Uuid;
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use log::info;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::reply::{self, Reply, Response};
 use warp::Filter;
-use serde::{Deserialize, Serialize};
-use serde_json::json;
 
-use crate::heartbeat::start_ping;
-
-#[derive(Deserialize, Serialize, Debug)]
-pub struct Job {
-    id: String,
-    input: serde_json::Value,
-}
-
-pub struct WorkerAPI<F> {
-    handler: Arc<Mutex<F>>,
+use super::job_store::{JobStatus, JobStore};
+use super::openapi::{docs_page, openapi_spec};
+use super::worker_config::WorkerConfig;
+
+/// How long `/runsync` waits for the job to finish before answering with its current status.
+pub const RUNSYNC_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// How long a job-take request from the worker waits for a job before getting `204 No Content`.
+const JOB_TAKE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Heartbeat interval of a worker serving the local API, short so cancellations apply quickly.
+const LOCAL_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Starts the local development API on `config.api_host:config.api_port`.
+///
+/// The server mirrors the serverless endpoint protocol with `/run`, `/runsync`, `/status/{id}`,
+/// `/stream/{id}`, `/cancel/{id}` and `/health` on top of an in-memory [`JobStore`], running at
+/// most `config.api_concurrency` jobs at once while further jobs wait in its queue. These routes
+/// are also served under any `/{endpoint_id}` prefix, so an [`Endpoint`](crate::endpoint::Endpoint)
+/// whose `base_url` is the server's address runs its jobs here. The API is
+/// described at `/openapi.json` and `/docs`, with `input_schema` as the job input schema. It also
+/// stands in for the platform under `/_worker`: the returned configuration has the worker take
+/// jobs from, post results to and send heartbeats to the server.
+pub fn serve_local_api(
+    config: &WorkerConfig,
+    input_schema: Option<&Value>,
+) -> anyhow::Result<(JoinHandle<()>, WorkerConfig)> {
+    let addr = (config.api_host.as_str(), config.api_port)
+        .to_socket_addrs()
+        .with_context(|| format!("could not resolve {}", config.api_host))?
+        .next()
+        .with_context(|| format!("no address found for {}", config.api_host))?;
+
+    let (addr, server) = warp::serve(local_api_routes(JobStore::new(config.api_concurrency), openapi_spec(input_schema)))
+        .try_bind_ephemeral(addr)
+        .with_context(|| format!("could not listen on {}", addr))?;
+    info!("Local API listening on http://{}", addr);
+
+    let worker_url = format!("http://{}/_worker", addr);
+    let worker_config = WorkerConfig {
+        job_get_url: Some(format!("{}/job-take", worker_url)),
+        job_done_url: format!("{}/job-done/$ID", worker_url),
+        job_stream_url: format!("{}/job-stream/$ID", worker_url),
+        job_progress_url: format!("{}/job-done/$ID", worker_url),
+        job_status_url: None,
+        ping_url: Some(format!("{}/ping", worker_url)),
+        ping_interval: LOCAL_PING_INTERVAL,
+        ..config.clone()
+    };
+    Ok((tokio::spawn(server), worker_config))
 }
 
-impl<F, Fut> WorkerAPI<F>
-where
-    F: Fn(Job) -> Fut + Send + 'static,
-    Fut: std::future::Future<Output = serde_json::Value> + Send + 'static,
-{
-    pub fn new(handler: F) -> Self {
-        // Start the heartbeat thread.
-        task::spawn(start_ping());
-
-        // Set the handler for processing jobs.
-        WorkerAPI {
-            handler: Arc::new(Mutex::new(handler)),
-        }
-    }
-
-    pub async fn run(&self, job: Job) -> Result<impl warp::Reply, warp::Rejection> {
-        let handler = self.handler.lock().unwrap();
-
-        // Process the job using the provided handler.
-        let job_results = handler(job).await;
-
-        // Return the results of the job processing.
-        Ok(warp::reply::json(&job_results))
-    }
-
-    pub async fn start_server(self, api_port: u16, api_concurrency: usize) {
-        let worker_api = Arc::new(self);
-
-        let job_route = warp::path!(String / "realtime")
-            .and(warp::post())
-            .and(warp::body::json())
-            .and_then(move |endpoint_id: String, job: Job| {
-                let worker_api = Arc::clone(&worker_api);
-                async move {
-                    let rp_endpoint_id = env::var("RUNPOD_ENDPOINT_ID").unwrap_or_default();
-
-                    if endpoint_id == rp_endpoint_id {
-                        worker_api.run(job).await
-                    } else {
-                        Err(warp::reject::not_found())
-                    }
+fn local_api_routes(
+    store: JobStore,
+    spec: Value,
+) -> impl Filter<Extract = (Response,), Error = warp::Rejection> + Clone {
+    let store = warp::any().map(move || store.clone());
+
+    let openapi = warp::path!("openapi.json")
+        .and(warp::get())
+        .map(move || reply::json(&spec).into_response());
+
+    let docs = warp::path!("docs")
+        .and(warp::get())
+        .map(|| reply::html(docs_page("/openapi.json")).into_response());
+
+    let run = warp::path!("run")
+        .and(warp::post())
+        .and(json_payload())
+        .and(store.clone())
+        .map(|request: Option<Value>, store: JobStore| match request {
+            Some(request) => {
+                let id = store.submit(request);
+                reply::json(&json!({ "id": id, "status": JobStatus::InQueue })).into_response()
+            }
+            None => invalid_body(),
+        });
+
+    let runsync = warp::path!("runsync")
+        .and(warp::post())
+        .and(json_payload())
+        .and(store.clone())
+        .then(|request: Option<Value>, store: JobStore| async move {
+            match request {
+                Some(request) => {
+                    let id = store.submit(request);
+                    found_or_404(store.wait_until_final(&id, RUNSYNC_TIMEOUT).await)
                 }
-            });
-
-        let routes = job_route;
-
-        warp::serve(routes).run(([0, 0, 0, 0], api_port)).await;
-    }
+                None => invalid_body(),
+            }
+        });
+
+    let status = warp::path!("status" / String)
+        .and(warp::get().or(warp::post()).unify())
+        .and(store.clone())
+        .map(|id: String, store: JobStore| found_or_404(store.status(&id)));
+
+    let stream = warp::path!("stream" / String)
+        .and(warp::get().or(warp::post()).unify())
+        .and(store.clone())
+        .map(|id: String, store: JobStore| found_or_404(store.drain_stream(&id)));
+
+    let cancel = warp::path!("cancel" / String)
+        .and(warp::post())
+        .and(store.clone())
+        .map(|id: String, store: JobStore| {
+            found_or_404(store.cancel(&id).map(|status| json!({ "id": id, "status": status })))
+        });
+
+    let health = warp::path!("health")
+        .and(warp::get())
+        .and(store.clone())
+        .map(|store: JobStore| reply::json(&health_body(&store)).into_response());
+
+    let job_take = warp::path!("_worker" / "job-take")
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(store.clone())
+        .then(|query: HashMap<String, String>, store: JobStore| async move {
+            let batch_size = query.get("batch_size").and_then(|size| size.parse().ok()).unwrap_or(1);
+            let mut jobs = store.take_wait(batch_size, JOB_TAKE_TIMEOUT).await;
+            match jobs.len() {
+                0 => StatusCode::NO_CONTENT.into_response(),
+                1 => reply::json(&jobs.remove(0)).into_response(),
+                _ => reply::json(&jobs).into_response(),
+            }
+        });
+
+    let job_done = warp::path!("_worker" / "job-done" / String)
+        .and(warp::post())
+        .and(json_payload())
+        .and(store.clone())
+        .map(|id: String, result: Option<Value>, store: JobStore| match result {
+            Some(result) => {
+                store.record_result(&id, &result);
+                StatusCode::OK.into_response()
+            }
+            None => invalid_body(),
+        });
+
+    let job_stream = warp::path!("_worker" / "job-stream" / String)
+        .and(warp::post())
+        .and(json_payload())
+        .and(store.clone())
+        .map(|id: String, result: Option<Value>, store: JobStore| match result {
+            Some(result) => {
+                store.record_stream(&id, &result);
+                StatusCode::OK.into_response()
+            }
+            None => invalid_body(),
+        });
+
+    let ping = warp::path!("_worker" / "ping")
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .and(store)
+        .map(|query: HashMap<String, String>, store: JobStore| {
+            let job_ids: Vec<&str> = query.get("job_id").map_or_else(Vec::new, |job_ids| job_ids.split(',').collect());
+            reply::json(&json!({ "cancelled_job_ids": store.take_cancelled(&job_ids) })).into_response()
+        });
+
+    let endpoint_routes = run
+        .or(runsync)
+        .unify()
+        .or(status)
+        .unify()
+        .or(stream)
+        .unify()
+        .or(cancel)
+        .unify()
+        .or(health)
+        .unify();
+    // The endpoint API prefixes its routes with the endpoint id, which the local API ignores.
+    let prefixed_endpoint_routes = warp::path::param::<String>()
+        .and(endpoint_routes.clone())
+        .map(|_endpoint_id: String, response: Response| response);
+
+    endpoint_routes
+        .or(prefixed_endpoint_routes)
+        .unify()
+        .or(job_take)
+        .unify()
+        .or(job_done)
+        .unify()
+        .or(job_stream)
+        .unify()
+        .or(ping)
+        .unify()
+        .or(openapi)
+        .unify()
+        .or(docs)
+        .unify()
 }
 
-// Example usage:
+/// JSON request body, gzip-compressed or not; `None` when it can't be read.
+fn json_payload() -> impl Filter<Extract = (Option<Value>,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("content-encoding")
+        .and(warp::body::bytes())
+        .map(|encoding: Option<String>, body: Bytes| {
+            if encoding.as_deref() != Some("gzip") {
+                return serde_json::from_slice(&body).ok();
+            }
+            let mut decoded = Vec::new();
+            GzDecoder::new(&body[..]).read_to_end(&mut decoded).ok()?;
+            serde_json::from_slice(&decoded).ok()
+        })
+}
 
-async fn handler(job: Job) -> serde_json::Value {
-    json!({"job_id": job.id, "job_input": job.input})
+fn invalid_body() -> Response {
+    reply::with_status(reply::json(&json!({ "error": "invalid JSON body" })), StatusCode::BAD_REQUEST).into_response()
 }
 
-#[tokio::main]
-async fn main() {
-    let api_port = env::var("API_PORT").unwrap_or_else(|_| "8000".to_string()).parse().unwrap();
-    let api_concurrency = env::var("API_CONCURRENCY")
-        .unwrap_or_else(|_| "1".to_string())
-        .parse()
-        .unwrap();
+fn found_or_404(body: Option<Value>) -> Response {
+    match body {
+        Some(body) => reply::json(&body).into_response(),
+        None => reply::with_status(reply::json(&json!({ "error": "job not found" })), StatusCode::NOT_FOUND).into_response(),
+    }
+}
 
-    let worker_api = WorkerAPI::new(handler);
-    worker_api.start_server(api_port, api_concurrency).await;
+fn health_body(store: &JobStore) -> Value {
+    let counts = store.counts();
+    let count = |status| counts.get(&status).copied().unwrap_or(0);
+    json!({
+        "concurrency": store.concurrency(),
+        "queueDepth": count(JobStatus::InQueue),
+        "jobs": {
+            "inQueue": count(JobStatus::InQueue),
+            "inProgress": count(JobStatus::InProgress),
+            "completed": count(JobStatus::Completed),
+            "failed": count(JobStatus::Failed),
+            "cancelled": count(JobStatus::Cancelled),
+        },
+    })
 }
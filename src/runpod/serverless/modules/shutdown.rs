@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use log::info;
+use tokio::signal;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Shared flag telling the worker subsystems to wind down.
+///
+/// Clones observe the same flag, so the worker loop, heartbeat and API server can all wait
+/// on one shutdown triggered by a signal or by the worker itself.
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Shutdown {
+            sender: Arc::new(sender),
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once the shutdown has been triggered.
+    pub async fn triggered(&self) {
+        let mut receiver = self.sender.subscribe();
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+
+    /// Spawns a task that triggers the shutdown on SIGTERM or SIGINT.
+    pub fn listen_for_signals(&self) -> JoinHandle<()> {
+        let shutdown = self.clone();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            shutdown.trigger();
+        })
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolves when the process receives SIGTERM (sent by RunPod when scaling down) or SIGINT.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+        tokio::select! {
+            _ = sigterm.recv() => info!("Received SIGTERM"),
+            _ = signal::ctrl_c() => info!("Received SIGINT"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = signal::ctrl_c().await;
+        info!("Received SIGINT");
+    }
+}
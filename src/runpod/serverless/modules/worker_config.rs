@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use dotenv::dotenv;
+use reqwest::Url;
+
+use super::worker_state::worker_id;
+
+/// Environment variables read by [`WorkerConfig`]. Each one can be overridden on the command
+/// line by the matching flag, e.g. `--rp_ping_interval 5000` for `RUNPOD_PING_INTERVAL`.
+pub const SETTINGS: &[&str] = &[
+    "RUNPOD_AI_API_KEY",
+    "RUNPOD_WEBHOOK_GET_JOB",
+    "RUNPOD_WEBHOOK_POST_OUTPUT",
+    "RUNPOD_WEBHOOK_POST_STREAM",
+    "RUNPOD_WEBHOOK_POST_PROGRESS",
+    "RUNPOD_WEBHOOK_PING",
+    "RUNPOD_WEBHOOK_JOB_STATUS",
+    "RUNPOD_ENDPOINT_ID",
+    "RUNPOD_PING_INTERVAL",
+    "RUNPOD_EXECUTION_TIMEOUT",
+    "RUNPOD_GZIP_THRESHOLD",
+    "RUNPOD_OUTBOX_DIR",
+    "API_HOST",
+    "API_PORT",
+    "API_CONCURRENCY",
+];
+
+/// Command line flag giving the local test input, see [`WorkerConfig::test_input`].
+pub const TEST_INPUT_FLAG: &str = "--test_input";
+
+/// Command line flag starting the local API server, see [`WorkerConfig::serve_api`].
+pub const SERVE_API_FLAG: &str = "--rp_serve_api";
+
+/// Settings of a serverless worker, read once at startup and handed to every subsystem.
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    /// Id of this worker (`RUNPOD_POD_ID`, a random id when unset).
+    pub worker_id: String,
+    /// Sent as the `Authorization` header of platform requests.
+    pub api_key: Option<String>,
+    /// Job-take endpoint; the worker runs local test jobs when unset.
+    pub job_get_url: Option<String>,
+    /// Job-done endpoint, `$ID` standing for the job id.
+    pub job_done_url: String,
+    /// Endpoint receiving the partial outputs of streaming jobs, `$ID` standing for the job id.
+    pub job_stream_url: String,
+    /// Endpoint receiving progress updates, `$ID` standing for the job id.
+    pub job_progress_url: String,
+    /// Job status endpoint polled for cancellations, `$ID` standing for the job id.
+    ///
+    /// Defaults to the endpoint API's status route when `RUNPOD_ENDPOINT_ID` is set; running
+    /// jobs are not cancelled by the platform when unset.
+    pub job_status_url: Option<String>,
+    /// Heartbeat endpoint; no heartbeat is sent when unset.
+    pub ping_url: Option<String>,
+    pub ping_interval: Duration,
+    /// Default job execution timeout, unbounded when unset.
+    pub execution_timeout: Option<Duration>,
+    /// Size in bytes above which posted results are gzip-compressed.
+    pub gzip_threshold: usize,
+    /// Directory holding results that could not be posted.
+    pub outbox_dir: PathBuf,
+    /// Local test input given with `--test_input`, as JSON or the path of a JSON file.
+    pub test_input: Option<String>,
+    /// Serve the local development API instead of taking jobs from the platform, set with
+    /// `--rp_serve_api`.
+    pub serve_api: bool,
+    /// Host the local API listens on (`API_HOST`).
+    pub api_host: String,
+    /// Port the local API listens on (`API_PORT`).
+    pub api_port: u16,
+    /// Maximum number of jobs the local API runs in parallel (`API_CONCURRENCY`).
+    pub api_concurrency: usize,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        WorkerConfig {
+            worker_id: worker_id(),
+            api_key: None,
+            job_get_url: None,
+            job_done_url: String::new(),
+            job_stream_url: String::new(),
+            job_progress_url: String::new(),
+            job_status_url: None,
+            ping_url: None,
+            ping_interval: Duration::from_millis(10000),
+            execution_timeout: None,
+            gzip_threshold: 1_000_000,
+            outbox_dir: PathBuf::from("result_outbox"),
+            test_input: None,
+            serve_api: false,
+            api_host: "localhost".to_string(),
+            api_port: 8000,
+            api_concurrency: 1,
+        }
+    }
+}
+
+impl WorkerConfig {
+    /// Loads the configuration from the environment, a `.env` file in the working directory
+    /// and the process arguments, in increasing order of precedence.
+    pub fn load() -> Result<Self, ConfigError> {
+        dotenv().ok();
+        WorkerConfig::from_sources(env::vars().collect(), env::args().skip(1))
+    }
+
+    /// Builds the configuration from the variables in `vars`, overridden by the flags in `args`.
+    ///
+    /// Besides the flags of the [`SETTINGS`], `args` may hold `--test_input` and `--rp_serve_api`.
+    /// Arguments that are not configuration flags are ignored.
+    pub fn from_sources<I>(mut vars: HashMap<String, String>, args: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut test_input = None;
+        let mut serve_api = false;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            if flag == SERVE_API_FLAG {
+                serve_api = match inline_value.as_deref() {
+                    None | Some("true") => true,
+                    Some("false") => false,
+                    Some(value) => return Err(ConfigError::invalid(SERVE_API_FLAG, value, "true or false")),
+                };
+                continue;
+            }
+            let setting = SETTINGS.iter().find(|var| flag_name(var) == flag);
+            if setting.is_none() && flag != TEST_INPUT_FLAG {
+                continue;
+            }
+            let value = match inline_value.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(ConfigError::MissingValue { flag }),
+            };
+            match setting {
+                Some(var) => {
+                    vars.insert(var.to_string(), value);
+                }
+                None => test_input = Some(value),
+            }
+        }
+
+        let worker_id = vars.get("RUNPOD_POD_ID").cloned().unwrap_or_else(worker_id);
+        let var = |name: &str| vars.get(name).filter(|value| !value.is_empty()).cloned();
+        let url = |name: &str| -> Result<Option<String>, ConfigError> {
+            let url = match var(name) {
+                Some(url) => url.replace("$RUNPOD_POD_ID", &worker_id),
+                None => return Ok(None),
+            };
+            match Url::parse(&url) {
+                Ok(_) => Ok(Some(url)),
+                Err(err) => Err(ConfigError::invalid(name, &url, &format!("a URL ({})", err))),
+            }
+        };
+
+        let job_get_url = url("RUNPOD_WEBHOOK_GET_JOB")?.map(|url| url.replace("$ID", &worker_id));
+        let job_done_url = match url("RUNPOD_WEBHOOK_POST_OUTPUT")? {
+            Some(url) => url,
+            None if job_get_url.is_some() => {
+                return Err(ConfigError::MissingSetting {
+                    name: "RUNPOD_WEBHOOK_POST_OUTPUT".to_string(),
+                    required_by: "RUNPOD_WEBHOOK_GET_JOB".to_string(),
+                })
+            }
+            None => String::new(),
+        };
+        let defaults = WorkerConfig::default();
+        Ok(WorkerConfig {
+            api_key: var("RUNPOD_AI_API_KEY"),
+            job_get_url,
+            job_stream_url: url("RUNPOD_WEBHOOK_POST_STREAM")?.unwrap_or_default(),
+            job_progress_url: url("RUNPOD_WEBHOOK_POST_PROGRESS")?.unwrap_or_else(|| job_done_url.clone()),
+            job_done_url,
+            job_status_url: match url("RUNPOD_WEBHOOK_JOB_STATUS")? {
+                Some(url) => Some(url),
+                None => var("RUNPOD_ENDPOINT_ID")
+                    .map(|endpoint_id| format!("{}/{}/status/$ID", crate::ENDPOINT_URL_BASE, endpoint_id)),
+            },
+            ping_url: url("RUNPOD_WEBHOOK_PING")?,
+            ping_interval: match var("RUNPOD_PING_INTERVAL") {
+                Some(interval) => match interval.parse::<u64>() {
+                    Ok(interval) if interval > 0 => Duration::from_millis(interval),
+                    _ => return Err(ConfigError::invalid("RUNPOD_PING_INTERVAL", &interval, "a positive number of milliseconds")),
+                },
+                None => defaults.ping_interval,
+            },
+            execution_timeout: match var("RUNPOD_EXECUTION_TIMEOUT") {
+                Some(timeout) => match timeout.parse::<u64>() {
+                    Ok(timeout) => Some(Duration::from_millis(timeout)).filter(|timeout| !timeout.is_zero()),
+                    Err(_) => return Err(ConfigError::invalid("RUNPOD_EXECUTION_TIMEOUT", &timeout, "a number of milliseconds")),
+                },
+                None => defaults.execution_timeout,
+            },
+            gzip_threshold: match var("RUNPOD_GZIP_THRESHOLD") {
+                Some(threshold) => threshold
+                    .parse::<usize>()
+                    .map_err(|_| ConfigError::invalid("RUNPOD_GZIP_THRESHOLD", &threshold, "a number of bytes"))?,
+                None => defaults.gzip_threshold,
+            },
+            outbox_dir: var("RUNPOD_OUTBOX_DIR").map(PathBuf::from).unwrap_or(defaults.outbox_dir),
+            test_input,
+            serve_api,
+            api_host: var("API_HOST").unwrap_or(defaults.api_host),
+            api_port: match var("API_PORT") {
+                Some(port) => port
+                    .parse::<u16>()
+                    .map_err(|_| ConfigError::invalid("API_PORT", &port, "a port number"))?,
+                None => defaults.api_port,
+            },
+            api_concurrency: match var("API_CONCURRENCY") {
+                Some(concurrency) => match concurrency.parse::<usize>() {
+                    Ok(concurrency) if concurrency > 0 => concurrency,
+                    _ => return Err(ConfigError::invalid("API_CONCURRENCY", &concurrency, "a positive number")),
+                },
+                None => defaults.api_concurrency,
+            },
+            worker_id,
+        })
+    }
+
+    /// Whether the worker runs local test jobs instead of taking jobs from the platform.
+    pub fn is_local_test(&self) -> bool {
+        self.job_get_url.is_none()
+    }
+
+    /// Whether undelivered results are kept in the outbox and retried, which is only the case
+    /// when taking jobs from the platform.
+    pub fn uses_outbox(&self) -> bool {
+        !self.is_local_test() && !self.serve_api
+    }
+
+    pub fn done_url(&self, job_id: &str) -> String {
+        self.job_done_url.replace("$ID", job_id)
+    }
+
+    pub fn stream_url(&self, job_id: &str) -> String {
+        self.job_stream_url.replace("$ID", job_id)
+    }
+
+    pub fn status_url(&self, job_id: &str) -> Option<String> {
+        self.job_status_url.as_ref().map(|url| url.replace("$ID", job_id))
+    }
+
+    pub fn progress_url(&self, job_id: &str) -> String {
+        self.job_progress_url.replace("$ID", job_id)
+    }
+}
+
+/// Command line flag overriding the environment variable `var`.
+pub fn flag_name(var: &str) -> String {
+    format!("--rp_{}", var.trim_start_matches("RUNPOD_").to_lowercase())
+}
+
+/// A malformed worker setting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// A flag was passed without a value.
+    MissingValue { flag: String },
+    /// A setting has a value that can't be used.
+    InvalidValue { name: String, value: String, expected: String },
+    /// A setting is unset although another one that needs it is set.
+    MissingSetting { name: String, required_by: String },
+}
+
+impl ConfigError {
+    fn invalid(name: &str, value: &str, expected: &str) -> Self {
+        ConfigError::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+            expected: expected.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::MissingValue { flag } => write!(f, "{} requires a value", flag),
+            ConfigError::MissingSetting { name, required_by } => write!(
+                f,
+                "{} (or {}) must be set when {} is set",
+                name,
+                flag_name(name),
+                required_by
+            ),
+            ConfigError::InvalidValue { name, value, expected } if name.starts_with("--") => {
+                write!(f, "invalid {} {:?}, expected {}", name, value, expected)
+            }
+            ConfigError::InvalidValue { name, value, expected } => write!(
+                f,
+                "invalid {} (or {}) {:?}, expected {}",
+                name,
+                flag_name(name),
+                value,
+                expected
+            ),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn reads_settings_from_vars() {
+        let config = WorkerConfig::from_sources(
+            vars(&[
+                ("RUNPOD_POD_ID", "pod-1"),
+                ("RUNPOD_WEBHOOK_GET_JOB", "https://api.example/job-take/$RUNPOD_POD_ID?worker=$ID"),
+                ("RUNPOD_WEBHOOK_POST_OUTPUT", "https://api.example/job-done/$RUNPOD_POD_ID/$ID"),
+                ("RUNPOD_PING_INTERVAL", "5000"),
+                ("RUNPOD_EXECUTION_TIMEOUT", "0"),
+            ]),
+            args(&[]),
+        )
+        .unwrap();
+
+        assert_eq!(config.worker_id, "pod-1");
+        assert_eq!(config.job_get_url.as_deref(), Some("https://api.example/job-take/pod-1?worker=pod-1"));
+        assert_eq!(config.done_url("job-1"), "https://api.example/job-done/pod-1/job-1");
+        assert_eq!(config.progress_url("job-1"), config.done_url("job-1"));
+        assert_eq!(config.ping_interval, Duration::from_millis(5000));
+        assert_eq!(config.execution_timeout, None);
+        assert!(!config.is_local_test());
+    }
+
+    #[test]
+    fn flags_override_vars_in_both_forms() {
+        let config = WorkerConfig::from_sources(
+            vars(&[("RUNPOD_PING_INTERVAL", "5000"), ("API_PORT", "8000")]),
+            args(&["--verbose", "--rp_ping_interval=2000", "--rp_api_port", "9000", "--test_input", "{}"]),
+        )
+        .unwrap();
+
+        assert_eq!(config.ping_interval, Duration::from_millis(2000));
+        assert_eq!(config.api_port, 9000);
+        assert_eq!(config.test_input.as_deref(), Some("{}"));
+        assert!(config.is_local_test());
+    }
+
+    #[test]
+    fn serve_api_flag_takes_an_optional_boolean() {
+        let serve_api = |flag: &str| WorkerConfig::from_sources(vars(&[]), args(&[flag])).map(|config| config.serve_api);
+
+        assert_eq!(serve_api("--rp_serve_api"), Ok(true));
+        assert_eq!(serve_api("--rp_serve_api=true"), Ok(true));
+        assert_eq!(serve_api("--rp_serve_api=false"), Ok(false));
+        assert_eq!(
+            serve_api("--rp_serve_api=yes").unwrap_err().to_string(),
+            "invalid --rp_serve_api \"yes\", expected true or false"
+        );
+    }
+
+    #[test]
+    fn job_status_url_defaults_to_the_endpoint_api() {
+        let config = WorkerConfig::from_sources(vars(&[("RUNPOD_ENDPOINT_ID", "ep")]), args(&[])).unwrap();
+        assert_eq!(config.status_url("job-1").as_deref(), Some("https://api.runpod.ai/v2/ep/status/job-1"));
+
+        let config = WorkerConfig::from_sources(vars(&[]), args(&[])).unwrap();
+        assert_eq!(config.status_url("job-1"), None);
+    }
+
+    #[test]
+    fn reports_missing_flag_values() {
+        let err = WorkerConfig::from_sources(vars(&[]), args(&["--rp_ping_interval"])).unwrap_err();
+        assert_eq!(err, ConfigError::MissingValue { flag: "--rp_ping_interval".to_string() });
+        assert_eq!(err.to_string(), "--rp_ping_interval requires a value");
+    }
+
+    #[test]
+    fn reports_invalid_values() {
+        let err = WorkerConfig::from_sources(vars(&[("RUNPOD_PING_INTERVAL", "0")]), args(&[])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid RUNPOD_PING_INTERVAL (or --rp_ping_interval) \"0\", expected a positive number of milliseconds"
+        );
+
+        let err = WorkerConfig::from_sources(vars(&[]), args(&["--rp_webhook_ping", "not a url"])).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidValue { name, .. } if name == "RUNPOD_WEBHOOK_PING"));
+
+        for (var, value) in [
+            ("RUNPOD_GZIP_THRESHOLD", "-1"),
+            ("API_PORT", "70000"),
+            ("API_CONCURRENCY", "0"),
+            ("RUNPOD_EXECUTION_TIMEOUT", "soon"),
+        ] {
+            let err = WorkerConfig::from_sources(vars(&[(var, value)]), args(&[])).unwrap_err();
+            assert!(matches!(err, ConfigError::InvalidValue { name, .. } if name == var), "{}", var);
+        }
+
+        let err = WorkerConfig::from_sources(vars(&[("RUNPOD_WEBHOOK_GET_JOB", "https://api.example/job-take/$ID")]), args(&[]))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ConfigError::MissingSetting {
+                name: "RUNPOD_WEBHOOK_POST_OUTPUT".to_string(),
+                required_by: "RUNPOD_WEBHOOK_GET_JOB".to_string(),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "RUNPOD_WEBHOOK_POST_OUTPUT (or --rp_webhook_post_output) must be set when RUNPOD_WEBHOOK_GET_JOB is set"
+        );
+    }
+}
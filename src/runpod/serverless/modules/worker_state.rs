@@ -1,48 +1,155 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+/// State the worker keeps for each job it holds.
+#[derive(Default)]
+struct JobState {
+    cancellation_token: CancellationToken,
+    last_progress_update: Option<Instant>,
+    /// Latest progress update held back by the rate limit.
+    pending_progress: Option<Value>,
+    /// Set once the handler has finished; no progress update is sent after that.
+    progress_closed: bool,
+}
+
 lazy_static::lazy_static! {
-    static ref CURRENT_JOB_ID: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    static ref JOBS: Arc<Mutex<BTreeMap<String, JobState>>> = Arc::new(Mutex::new(BTreeMap::new()));
     static ref WORKER_ID: String = env::var("RUNPOD_POD_ID").unwrap_or_else(|_| Uuid::new_v4().to_string());
 }
 
-pub fn get_auth_header() -> String {
-    env::var("RUNPOD_AI_API_KEY").unwrap_or_default()
+pub fn worker_id() -> String {
+    WORKER_ID.clone()
+}
+
+/// Ids of the jobs the worker is currently processing.
+pub fn get_job_ids() -> Vec<String> {
+    JOBS.lock().unwrap().keys().cloned().collect()
+}
+
+pub fn add_job_id(job_id: &str) {
+    JOBS.lock().unwrap().entry(job_id.to_string()).or_default();
+}
+
+pub fn remove_job_id(job_id: &str) {
+    JOBS.lock().unwrap().remove(job_id);
 }
 
-pub fn job_get_url() -> String {
-    env::var("RUNPOD_WEBHOOK_GET_JOB")
-        .unwrap_or_default()
-        .replace("$ID", &*WORKER_ID)
+pub fn get_cancellation_token(job_id: &str) -> Option<CancellationToken> {
+    JOBS.lock().unwrap().get(job_id).map(|job| job.cancellation_token.clone())
 }
 
-pub fn job_done_url_template() -> String {
-    env::var("RUNPOD_WEBHOOK_POST_OUTPUT")
-        .unwrap_or_default()
-        .replace("$RUNPOD_POD_ID", &*WORKER_ID)
+/// Cancels an in-flight job, returning `false` if the worker doesn't hold it.
+pub fn cancel_job(job_id: &str) -> bool {
+    match JOBS.lock().unwrap().get(job_id) {
+        Some(job) => {
+            job.cancellation_token.cancel();
+            true
+        }
+        None => false,
+    }
 }
 
-pub fn webhook_ping() -> Option<String> {
-    env::var("RUNPOD_WEBHOOK_PING")
-        .map(|url| url.replace("$RUNPOD_POD_ID", &*WORKER_ID))
-        .ok()
+/// When a progress update may be posted, see [`schedule_progress_update`].
+#[derive(Debug, PartialEq)]
+pub enum ProgressSchedule {
+    /// Post the update now.
+    Now,
+    /// The update is pending; take it with [`take_pending_progress`] after the delay.
+    After(Duration),
+    /// The update replaced an earlier pending one, whose delivery is already scheduled.
+    Coalesced,
+    /// The job's handler has finished, so the update is dropped.
+    Closed,
 }
 
-pub fn ping_interval() -> u64 {
-    env::var("RUNPOD_PING_INTERVAL")
-        .map(|interval| interval.parse::<u64>().unwrap_or(10000))
-        .unwrap_or(10000)
+/// Records a progress update for `job_id`, keeping it as the job's pending update when one was
+/// sent less than `min_interval` ago. Jobs the worker doesn't hold are never limited.
+pub fn schedule_progress_update(job_id: &str, progress: &Value, min_interval: Duration) -> ProgressSchedule {
+    let mut jobs = JOBS.lock().unwrap();
+    let job = match jobs.get_mut(job_id) {
+        Some(job) => job,
+        None => return ProgressSchedule::Now,
+    };
+    if job.progress_closed {
+        return ProgressSchedule::Closed;
+    }
+
+    let now = Instant::now();
+    let elapsed = job.last_progress_update.map(|last_update| now.duration_since(last_update));
+    match elapsed {
+        Some(elapsed) if elapsed < min_interval => {
+            let scheduled = job.pending_progress.replace(progress.clone()).is_some();
+            if scheduled {
+                ProgressSchedule::Coalesced
+            } else {
+                ProgressSchedule::After(min_interval - elapsed)
+            }
+        }
+        _ => {
+            job.last_progress_update = Some(now);
+            ProgressSchedule::Now
+        }
+    }
 }
 
-pub fn get_current_job_id() -> Option<String> {
-    CURRENT_JOB_ID.lock().unwrap().clone()
+/// Takes the pending progress update of `job_id`, recording it as sent. `None` when the job
+/// has no pending update, its progress was closed or the worker no longer holds it.
+pub fn take_pending_progress(job_id: &str) -> Option<Value> {
+    let mut jobs = JOBS.lock().unwrap();
+    let job = jobs.get_mut(job_id)?;
+    let progress = job.pending_progress.take()?;
+    job.last_progress_update = Some(Instant::now());
+    Some(progress)
 }
 
-pub fn get_done_url() -> String {
-    job_done_url_template().replace("$ID", &*get_current_job_id().unwrap())
+/// Drops the pending progress update of `job_id` and refuses further ones, so no progress is
+/// posted once its handler has finished and its result is on the way.
+pub fn close_progress(job_id: &str) {
+    if let Some(job) = JOBS.lock().unwrap().get_mut(job_id) {
+        job.pending_progress = None;
+        job.progress_closed = true;
+    }
 }
 
-pub fn set_job_id(new_job_id: Option<String>) {
-    *CURRENT_JOB_ID.lock().unwrap() = new_job_id;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn holds_back_and_coalesces_fast_updates() {
+        let job_id = "progress-coalesced";
+        add_job_id(job_id);
+
+        assert_eq!(schedule_progress_update(job_id, &json!(1), MIN_INTERVAL), ProgressSchedule::Now);
+        assert!(matches!(schedule_progress_update(job_id, &json!(2), MIN_INTERVAL), ProgressSchedule::After(_)));
+        assert_eq!(schedule_progress_update(job_id, &json!(3), MIN_INTERVAL), ProgressSchedule::Coalesced);
+        assert_eq!(take_pending_progress(job_id), Some(json!(3)));
+        assert_eq!(take_pending_progress(job_id), None);
+
+        remove_job_id(job_id);
+    }
+
+    #[test]
+    fn drops_held_back_updates_once_the_handler_finished() {
+        let job_id = "progress-closed";
+        add_job_id(job_id);
+
+        assert_eq!(schedule_progress_update(job_id, &json!("99%"), MIN_INTERVAL), ProgressSchedule::Now);
+        assert!(matches!(schedule_progress_update(job_id, &json!("100%"), MIN_INTERVAL), ProgressSchedule::After(_)));
+        close_progress(job_id);
+
+        // The delayed send finds nothing to post, and later updates are refused.
+        assert_eq!(take_pending_progress(job_id), None);
+        assert_eq!(schedule_progress_update(job_id, &json!("late"), Duration::ZERO), ProgressSchedule::Closed);
+
+        remove_job_id(job_id);
+    }
 }
@@ -0,0 +1,2 @@
+pub mod rp_cleanup;
+pub mod rp_download;
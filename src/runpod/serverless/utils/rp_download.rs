@@ -1,18 +1,15 @@
 use std::fs;
 use std::io::Write;
-use std::path::Path;
 use std::sync::Arc;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::Url;
 use tokio::task::JoinHandle;
 use tokio::{runtime::Runtime, task};
 use uuid::Uuid;
-use zip::ZipArchive;
 
 #[async_trait]
-trait Downloader {
+pub trait Downloader {
     async fn download_file(&self, url: &str) -> Result<String>;
     async fn download_files_from_urls(&self, job_id: &str, urls: &[&str]) -> Vec<String>;
 }
@@ -29,26 +26,17 @@ impl RpDownloader {
     }
 }
 
+impl Default for RpDownloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl Downloader for RpDownloader {
     async fn download_file(&self, url: &str) -> Result<String> {
         let response = self.client.get(url).send().await?;
 
-        let content_disposition = response
-            .headers()
-            .get("content-disposition")
-            .and_then(|v| v.to_str().ok())
-            .unwrap_or_default();
-
-        let file_name = if !content_disposition.is_empty() {
-            content_disposition
-        } else {
-            Path::new(&Url::parse(url)?.path())
-                .file_name()
-                .and_then(|f| f.to_str())
-                .unwrap_or_default()
-        };
-
         let output_file_path = format!("job_files/{}", Uuid::new_v4());
         let mut output_file = fs::File::create(&output_file_path)?;
         output_file.write_all(&response.bytes().await?)?;
@@ -56,7 +44,7 @@ impl Downloader for RpDownloader {
         Ok(output_file_path)
     }
 
-    async fn download_files_from_urls(&self, job_id: &str, urls: &[&str]) -> Vec<String> {
+    async fn download_files_from_urls(&self, _job_id: &str, urls: &[&str]) -> Vec<String> {
         let client = Arc::new(self.client.clone());
         let mut tasks: Vec<JoinHandle<Result<String>>> = Vec::new();
 
@@ -0,0 +1,426 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::future::Future;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::{Fuse, FusedFuture, FutureExt};
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::Stream;
+use log::{debug, error, info, warn};
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+use super::modules::cancellation::start_cancel_poll;
+use super::modules::heartbeat::{start_ping_with_hook, DEFAULT_MAX_FAILURES};
+use super::modules::http_client::HttpClientConfig;
+use super::modules::job_context::JobContext;
+use super::modules::job::{check_local_result, get_jobs, get_local, run_job, run_job_stream, send_result};
+use super::modules::job_result::JobResult;
+use super::modules::openapi::input_schema_for;
+use super::modules::outbox;
+use super::modules::retry::backoff_delay;
+use super::modules::rp_fastapi::serve_local_api;
+use super::modules::shutdown::Shutdown;
+use super::modules::worker_config::WorkerConfig;
+use super::modules::worker_state::{add_job_id, close_progress, get_job_ids, remove_job_id};
+use super::utils::rp_cleanup::clean;
+
+/// Exit status of a worker process that stopped to be refreshed, so the container is restarted
+/// with a clean state.
+pub const REFRESH_EXIT_CODE: i32 = 75;
+
+/// Delay before fetching jobs again after a failed fetch, doubled on every further failure.
+const FETCH_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+/// Longest delay between fetches while fetching keeps failing.
+const FETCH_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Options controlling how the worker takes and runs jobs.
+pub struct StartConfig {
+    /// Return the list of all streamed outputs as the final output of a streaming job.
+    pub return_aggregate_stream: bool,
+    /// Maximum number of jobs processed in parallel.
+    ///
+    /// Each job runs as its own task on the Tokio runtime, so jobs only run on separate threads
+    /// with the multi-threaded runtime; handlers doing blocking work should still move it to
+    /// [`tokio::task::spawn_blocking`] to keep their runtime thread free.
+    pub concurrency: usize,
+    /// Number of jobs requested from the platform per fetch.
+    ///
+    /// Jobs beyond the current concurrency wait in the worker's queue until a slot frees up.
+    pub job_batch_size: usize,
+    /// Called with the current concurrency, returning the new one, each time the worker looks
+    /// for jobs to start: at startup and whenever a fetch completes or a job finishes.
+    ///
+    /// Lets the worker scale its parallelism with load, e.g. based on free GPU memory.
+    pub concurrency_modifier: Option<Box<dyn Fn(usize) -> usize + Send + Sync>>,
+    /// How long jobs already taken may keep running after SIGTERM before the worker exits.
+    pub shutdown_grace_period: Duration,
+    /// Consecutive failed heartbeats after which `on_heartbeat_failure` is called.
+    pub heartbeat_max_failures: u32,
+    /// Called with the failure count when heartbeats keep failing; logs an error when unset.
+    pub on_heartbeat_failure: Option<Box<dyn Fn(u32) + Send>>,
+    /// Refresh the worker after every job, as if each handler had returned `refresh_worker`.
+    pub refresh_worker: bool,
+    /// Settings of the client used for every call to the platform.
+    pub http_client: HttpClientConfig,
+    /// Worker settings, loaded with [`WorkerConfig::load`] when unset.
+    pub worker_config: Option<WorkerConfig>,
+    /// JSON schema of the handler input, shown in the local API's OpenAPI document.
+    ///
+    /// Set it with [`StartConfig::with_input_schema`].
+    pub input_schema: Option<Value>,
+}
+
+impl Default for StartConfig {
+    fn default() -> Self {
+        StartConfig {
+            return_aggregate_stream: false,
+            concurrency: 1,
+            job_batch_size: 1,
+            concurrency_modifier: None,
+            shutdown_grace_period: Duration::from_secs(10),
+            heartbeat_max_failures: DEFAULT_MAX_FAILURES,
+            on_heartbeat_failure: None,
+            refresh_worker: false,
+            http_client: HttpClientConfig::default(),
+            worker_config: None,
+            input_schema: None,
+        }
+    }
+}
+
+impl StartConfig {
+    /// Describes the handler input as `T` in the local API's OpenAPI document.
+    pub fn with_input_schema<T: JsonSchema>(mut self) -> Self {
+        self.input_schema = Some(input_schema_for::<T>());
+        self
+    }
+}
+
+/// Starts the serverless worker and processes jobs with `handler` until shutdown.
+///
+/// Each job's `input` is deserialized into `T` before being passed to `handler` together with
+/// the job's [`JobContext`], and the handler's `R` output is serialized back to JSON for the platform.
+///
+/// When `RUNPOD_WEBHOOK_GET_JOB` is not set the worker runs the local test jobs given with
+/// `--test_input` or in `test_input.json`, prints their results and returns, exiting with
+/// status 1 if a job failed or didn't match its `expected_output` (see [`get_local`] and
+/// [`check_local_result`]). An invalid [`WorkerConfig`] is reported and exits the
+/// process with status 1.
+///
+/// With `--rp_serve_api` the worker takes its jobs from a local development API instead, see
+/// [`serve_local_api`].
+///
+/// A job requesting a refresh (see [`StartConfig::refresh_worker`]) stops the worker from taking
+/// new jobs; once the jobs it holds are done it cleans up and exits with [`REFRESH_EXIT_CODE`].
+/// Refresh requests are only logged with `--rp_serve_api`, so the local API keeps serving.
+pub async fn start<F, Fut, T, R, E>(handler: F)
+where
+    F: Fn(T, JobContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<R, E>> + Send + 'static,
+    T: DeserializeOwned + Send + 'static,
+    R: Serialize + Send + 'static,
+    E: Display + Send + 'static,
+{
+    start_with_config(handler, StartConfig::default()).await;
+}
+
+/// Same as [`start`], with the worker behaviour customized by `config`.
+pub async fn start_with_config<F, Fut, T, R, E>(handler: F, config: StartConfig)
+where
+    F: Fn(T, JobContext) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<R, E>> + Send + 'static,
+    T: DeserializeOwned + Send + 'static,
+    R: Serialize + Send + 'static,
+    E: Display + Send + 'static,
+{
+    let handler = Arc::new(handler);
+    run_worker(config, move |client, worker_config, job| {
+        let handler = handler.clone();
+        async move { run_job(client, worker_config, &*handler, job).await }
+    })
+    .await;
+}
+
+/// Starts the serverless worker with a streaming `handler`.
+///
+/// Every item yielded by the handler is posted to `RUNPOD_WEBHOOK_POST_STREAM` as it is
+/// produced. With `config.return_aggregate_stream` the final job output is the list of all items.
+pub async fn start_stream<F, S, T, R, E>(handler: F, config: StartConfig)
+where
+    F: Fn(T, JobContext) -> S + Send + Sync + 'static,
+    S: Stream<Item = Result<R, E>> + Send + 'static,
+    T: DeserializeOwned + Send + 'static,
+    R: Serialize + Send + 'static,
+    E: Display + Send + 'static,
+{
+    let handler = Arc::new(handler);
+    let return_aggregate_stream = config.return_aggregate_stream;
+    run_worker(config, move |client, worker_config, job| {
+        let handler = handler.clone();
+        async move { run_job_stream(client, worker_config, &*handler, job, return_aggregate_stream).await }
+    })
+    .await;
+}
+
+async fn run_worker<P, Fut>(mut config: StartConfig, process_job: P)
+where
+    P: Fn(Arc<Client>, Arc<WorkerConfig>, Value) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = JobResult> + Send + 'static,
+{
+    let mut worker_config = match config.worker_config.take().map_or_else(WorkerConfig::load, Ok) {
+        Ok(worker_config) => worker_config,
+        Err(err) => {
+            error!("Invalid worker configuration: {}", err);
+            eprintln!("Invalid worker configuration: {}", err);
+            process::exit(1);
+        }
+    };
+    let local_api = if worker_config.serve_api {
+        match serve_local_api(&worker_config, config.input_schema.as_ref()) {
+            Ok((local_api, local_api_config)) => {
+                worker_config = local_api_config;
+                Some(local_api)
+            }
+            Err(err) => {
+                error!("Error while starting the local API: {:#}", err);
+                eprintln!("Error while starting the local API: {:#}", err);
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let worker_config = Arc::new(worker_config);
+    let client = match config.http_client.build_for(&worker_config) {
+        Ok(client) => Arc::new(client),
+        Err(err) => {
+            error!("Error while building the HTTP client: {:?}", err);
+            return;
+        }
+    };
+    let is_local_test = worker_config.is_local_test();
+    let mut concurrency = config.concurrency.max(1);
+    if local_api.is_some() {
+        // The local API decides how many jobs run at once, see `WorkerConfig::api_concurrency`.
+        concurrency = concurrency.max(worker_config.api_concurrency);
+    }
+
+    info!("Starting worker");
+    let on_heartbeat_failure = config.on_heartbeat_failure.take().unwrap_or_else(|| {
+        Box::new(|failures| error!("Heartbeat failed {} times in a row, the worker may be marked unhealthy", failures))
+    });
+    let heartbeat = start_ping_with_hook(
+        client.clone(),
+        worker_config.clone(),
+        config.heartbeat_max_failures,
+        on_heartbeat_failure,
+    );
+    let shutdown = Shutdown::new();
+    let signals = shutdown.listen_for_signals();
+    let outbox = worker_config
+        .uses_outbox()
+        .then(|| outbox::start_retry_loop(client.clone(), worker_config.clone()));
+    let cancel_poll = (!is_local_test && worker_config.job_status_url.is_some())
+        .then(|| start_cancel_poll(client.clone(), worker_config.clone()));
+
+    let refresh_worker = config.refresh_worker;
+    let process_job = Arc::new(process_job);
+    let handle_job = |job_id: String, job: Value| {
+        let client = client.clone();
+        let worker_config = worker_config.clone();
+        let process_job = process_job.clone();
+        tokio::spawn(async move {
+            let mut job_result = process_job(client.clone(), worker_config.clone(), job.clone()).await;
+            close_progress(&job_id);
+            if refresh_worker {
+                job_result = job_result.with_stop_pod();
+            }
+            send_result(client, &worker_config, &job_result, &job).await;
+            remove_job_id(&job_id);
+            let passed = !is_local_test || check_local_result(&job, &job_result);
+            (job_result, passed)
+        })
+    };
+
+    // The pending fetch is kept across iterations so a job the platform has already handed
+    // out is never dropped because another job finished first.
+    let mut fetch = Box::pin(Fuse::terminated());
+    let mut fetch_backoff = Box::pin(Fuse::terminated());
+    let mut fetch_failures = 0;
+    let mut queue = VecDeque::new();
+    let mut in_flight = FuturesUnordered::new();
+    let mut accepting_jobs = !is_local_test;
+    let mut finished_jobs = 0;
+    let mut failed_jobs = 0;
+    let mut refreshing = false;
+    let mut grace_period = Box::pin(Fuse::terminated());
+    let job_batch_size = config.job_batch_size.max(1);
+
+    if is_local_test {
+        match get_local(&worker_config) {
+            Ok(jobs) => jobs.into_iter().for_each(|job| enqueue(&mut queue, job)),
+            Err(err) => {
+                error!("Error while loading local test jobs: {:#}", err);
+                eprintln!("Local test failed: {:#}", err);
+                process::exit(1);
+            }
+        }
+    }
+
+    loop {
+        if let Some(concurrency_modifier) = &config.concurrency_modifier {
+            let new_concurrency = concurrency_modifier(concurrency).max(1);
+            if new_concurrency != concurrency {
+                debug!("Concurrency changed from {} to {}", concurrency, new_concurrency);
+                concurrency = new_concurrency;
+            }
+        }
+
+        while in_flight.len() < concurrency {
+            match queue.pop_front() {
+                Some((job_id, job)) => in_flight.push(handle_job(job_id, job)),
+                None => break,
+            }
+        }
+
+        if accepting_jobs
+            && fetch.is_terminated()
+            && fetch_backoff.is_terminated()
+            && queue.is_empty()
+            && in_flight.len() < concurrency
+        {
+            fetch.set(get_jobs(client.clone(), &worker_config, job_batch_size).fuse());
+        }
+
+        if !accepting_jobs && queue.is_empty() && in_flight.is_empty() {
+            break;
+        }
+
+        tokio::select! {
+            jobs = &mut fetch, if !fetch.is_terminated() => match jobs {
+                Ok(jobs) => {
+                    fetch_failures = 0;
+                    jobs.into_iter().for_each(|job| enqueue(&mut queue, job));
+                }
+                Err(err) => {
+                    fetch_failures += 1;
+                    let delay = backoff_delay(fetch_failures, FETCH_RETRY_BASE_DELAY, FETCH_RETRY_MAX_DELAY);
+                    error!("Error while getting jobs, retrying in {:?}: {:#}", delay, err);
+                    fetch_backoff.set(sleep(delay).fuse());
+                }
+            },
+            _ = &mut fetch_backoff, if !fetch_backoff.is_terminated() => {}
+            Some(finished) = in_flight.next() => {
+                finished_jobs += 1;
+                let (job_result, passed) = match finished {
+                    Ok(finished) => finished,
+                    Err(err) => {
+                        error!("Job task failed: {}", err);
+                        failed_jobs += 1;
+                        continue;
+                    }
+                };
+                if !passed {
+                    failed_jobs += 1;
+                }
+                if job_result.is_stop_pod() && worker_config.serve_api {
+                    info!("Refresh requested, ignored while serving the local API");
+                } else if job_result.is_stop_pod() && !refreshing {
+                    info!("Refresh requested, finishing {} remaining job(s)", queue.len() + in_flight.len());
+                    refreshing = true;
+                    accepting_jobs = false;
+                    fetch.set(Fuse::terminated());
+                }
+            }
+            _ = shutdown.triggered(), if grace_period.is_terminated() => {
+                info!(
+                    "Shutting down, waiting up to {:?} for {} job(s) to finish",
+                    config.shutdown_grace_period,
+                    queue.len() + in_flight.len()
+                );
+                accepting_jobs = false;
+                fetch.set(Fuse::terminated());
+                grace_period.set(sleep(config.shutdown_grace_period).fuse());
+            }
+            _ = &mut grace_period, if !grace_period.is_terminated() => {
+                warn!("Shutdown grace period elapsed, abandoning jobs {:?}", get_job_ids());
+                in_flight.iter().for_each(JoinHandle::abort);
+                break;
+            }
+        }
+    }
+
+    signals.abort();
+    if let Some(outbox) = outbox {
+        outbox.abort();
+    }
+    if let Some(cancel_poll) = cancel_poll {
+        cancel_poll.abort();
+    }
+    if let Some(local_api) = local_api {
+        local_api.abort();
+    }
+    heartbeat.stop().await;
+
+    if is_local_test {
+        println!("Local testing complete: {} of {} job(s) failed", failed_jobs, finished_jobs);
+        if failed_jobs > 0 {
+            process::exit(1);
+        }
+    }
+    if refreshing {
+        clean(None);
+        info!("Worker stopped for refresh");
+        process::exit(REFRESH_EXIT_CODE);
+    }
+    info!("Worker stopped");
+}
+
+/// Queues `job` to be processed, tracking it as held by the worker.
+fn enqueue(queue: &mut VecDeque<(String, Value)>, job: Value) {
+    match job["id"].as_str() {
+        Some(job_id) => {
+            add_job_id(job_id);
+            queue.push_back((job_id.to_string(), job));
+        }
+        None => warn!("Received job without an id, skipping: {:?}", job),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    async fn handler(input: Value, _context: JobContext) -> Result<Value, String> {
+        Ok(input)
+    }
+
+    fn stream_handler(input: Value, _context: JobContext) -> impl Stream<Item = Result<Value, String>> {
+        stream::iter(vec![Ok(input)])
+    }
+
+    #[test]
+    fn start_futures_can_be_spawned() {
+        let config = || StartConfig {
+            concurrency_modifier: Some(Box::new(|concurrency| concurrency)),
+            on_heartbeat_failure: Some(Box::new(|_| {})),
+            ..StartConfig::default()
+        };
+
+        assert_send(&start(handler));
+        assert_send(&start_with_config(handler, config()));
+        assert_send(&start_stream(stream_handler, config()));
+    }
+}